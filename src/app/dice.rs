@@ -1,14 +1,28 @@
+mod coc;
+mod dnd;
+mod export;
+mod expr;
+mod fair;
+mod filter;
+mod history;
+mod luck;
+mod notation;
+mod rng;
+mod session;
+mod sha256;
+mod stats;
+
 use eframe::egui;
 use rand::Rng;
 use std::default::Default;
-use std::mem::MaybeUninit;
 use super::sound::SoundPlayer;
+use notation::ParseError;
 
-const DICE_NUM: usize = 5;
-const DICE_TYPE: [i32; DICE_NUM] = [4, 6, 12, 20, 100];
+/// Dice types listed in the Selections panel before the user adds or removes any.
+const DEFAULT_DICE_TYPE: [i32; 7] = [4, 6, 8, 10, 12, 20, 100];
 
 pub struct DiceWrapper {
-    dice_feature: DiceFeature<DICE_NUM>,
+    dice_feature: DiceFeature,
 }
 
 impl DiceWrapper {
@@ -23,108 +37,771 @@ impl DiceWrapper {
     }
 }
 
-struct RollRecord<const N: usize> {
-    records: [Vec<i32>; N],
-    state: DicesState<N>,
+struct RollRecord {
+    /// Rolled dice, one `Vec` for each group of `state.groups`.
+    records: Vec<Vec<DieRecord>>,
+    state: DicesState,
 
-    time: chrono::NaiveTime,
+    time: chrono::DateTime<chrono::Local>,
     description: String,
+    /// What the roll was for, e.g. the skill of a check or `STR` of an investigator.
+    label: Option<String>,
     total: i32,
+
+    percentile: Option<coc::PercentileRoll>,
+    check: Option<coc::SkillCheck>,
+    sanity: Option<coc::SanityCheck>,
+    abilities: Option<dnd::AbilityScores>,
+    /// Every roll of `N#...`, empty for a single roll. The total is their sum.
+    repeats: Vec<RollRecord>,
+    /// Of a single roll of `state`, when it could be computed.
+    distribution: Option<std::rc::Rc<stats::Distribution>>,
+    /// Where a seeded roll started.
+    replay: Option<rng::Replay>,
+    fair: Option<fair::FairRoll>,
+    /// Every number the random source gave out for the roll.
+    tape: Vec<u64>,
+}
+
+impl RollRecord {
+    pub fn new(state: DicesState, records: Vec<Vec<DieRecord>>, total: i32) -> Box<RollRecord> {
+        Box::new(RollRecord {
+            records,
+            description: state.gen_description(),
+            label: None,
+            state,
+            time: chrono::Local::now(),
+            total,
+            percentile: None,
+            check: None,
+            sanity: None,
+            abilities: None,
+            repeats: Vec::new(),
+            distribution: None,
+            replay: None,
+            fair: None,
+            tape: Vec::new(),
+        })
+    }
+
+    /// Rolls the same thing again with `rd`, which gives the same result when `rd`
+    /// is where this roll started.
+    pub fn rolled_again(&self, rd: &mut impl Rng) -> Option<Box<RollRecord>> {
+        history::Recipe::of(self)?.roll(rd)
+    }
+
+    /// Rolls again from where a seeded roll started.
+    pub fn replayed(&self) -> Option<Box<RollRecord>> {
+        self.rolled_again(&mut self.replay?.rng())
+    }
+
+    /// The result as shown to the user, e.g. `14` or `3 successes, glitch`.
+    pub fn result_text(&self) -> String {
+        if let Some(sanity) = &self.sanity {
+            return format!("-{} SAN {}", sanity.loss, sanity.new_san());
+        }
+        if let Some(abilities) = &self.abilities {
+            let scores: Vec<String> = abilities.scores().map(|s| s.to_string()).collect();
+            return scores.join(" ");
+        }
+        if !self.repeats.is_empty() {
+            let results: Vec<String> = self.repeats.iter().map(|r| r.result_text()).collect();
+            return results.join(", ");
+        }
+        if !self.state.is_pool() {
+            return self.total.to_string();
+        }
+        let mut s = format!(
+            "{} success{}",
+            self.total,
+            if self.total.abs() == 1 { "" } else { "es" }
+        );
+        if let Some(mishap) = self.mishap() {
+            s.push_str(", ");
+            s.push_str(mishap.name());
+        }
+        s
+    }
+
+    /// Whether the total met the target, and by how much. Repeated rolls have their own.
+    pub fn outcome(&self) -> Option<(bool, i32)> {
+        if !self.repeats.is_empty() {
+            return None;
+        }
+        self.state
+            .target
+            .map(|t| (t.matches(self.total), t.margin(self.total)))
+    }
+
+    pub fn mishap(&self) -> Option<PoolMishap> {
+        self.state
+            .groups
+            .iter()
+            .zip(&self.records)
+            .filter_map(|(g, dice)| g.mishap(dice))
+            .max()
+    }
+}
+
+#[derive(Clone)]
+struct DieRecord {
+    /// Sum of `rolls`.
+    value: i32,
+    /// The first roll followed by every explosion of this dice.
+    rolls: Vec<i32>,
+    /// Earlier rolls thrown away by a reroll rule, oldest first.
+    rerolled: Vec<i32>,
+    /// Dropped by a keep/drop rule, the value does not count.
+    dropped: bool,
+}
+
+impl DieRecord {
+    pub fn new(value: i32) -> DieRecord {
+        DieRecord {
+            value,
+            rolls: vec![value],
+            rerolled: Vec::new(),
+            dropped: false,
+        }
+    }
+
+    pub fn push_roll(&mut self, value: i32) {
+        self.value += value;
+        self.rolls.push(value);
+    }
+
+    /// Throw the current roll away for `value`, before any explosion happens.
+    pub fn reroll(&mut self, value: i32) {
+        debug_assert_eq!(self.rolls.len(), 1);
+        self.rerolled.push(self.value);
+        self.value = value;
+        self.rolls[0] = value;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A condition on a value such as `>=9`.
+#[derive(Clone, Copy, PartialEq)]
+struct Compare {
+    op: CompareOp,
+    value: i32,
+}
+
+impl Compare {
+    pub fn matches(&self, v: i32) -> bool {
+        match self.op {
+            CompareOp::Eq => v == self.value,
+            CompareOp::Gt => v > self.value,
+            CompareOp::Ge => v >= self.value,
+            CompareOp::Lt => v < self.value,
+            CompareOp::Le => v <= self.value,
+        }
+    }
+
+    /// Like `notation`, but a plain number for `=`, e.g. `1` in `r1`.
+    pub fn short_notation(&self) -> String {
+        match self.op {
+            CompareOp::Eq => self.value.to_string(),
+            _ => self.notation(),
+        }
+    }
+
+    /// How far `v` is beyond the value, negative when it falls short. `<` and `<=` count
+    /// downwards, and `=` gives minus the distance.
+    pub fn margin(&self, v: i32) -> i32 {
+        match self.op {
+            CompareOp::Eq => -(v - self.value).abs(),
+            CompareOp::Gt | CompareOp::Ge => v - self.value,
+            CompareOp::Lt | CompareOp::Le => self.value - v,
+        }
+    }
+
+    pub fn notation(&self) -> String {
+        format!("{}{}", self.op.symbol(), self.value)
+    }
+}
+
+impl CompareOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+        }
+    }
+}
+
+/// Roll again when a dice shows an exploding face, e.g. `d6!`, `d6!!` or `d10!>=9`.
+///
+/// Every roll in the chain is kept in `DieRecord::rolls` and the dice is worth their sum.
+/// A compounding chain (`!!`) counts as one single roll for the success counting of
+/// a pool, an exploding one counts each roll on its own.
+#[derive(Clone, Copy, PartialEq)]
+struct ExplodeRule {
+    /// Which faces explode, the max face if `None`.
+    on: Option<Compare>,
+    compound: bool,
+}
+
+/// Stop an explosion chain after so many extra rolls, whatever the dice shows.
+const MAX_EXPLOSIONS: usize = 100;
+
+/// Roll a dice again while, or only once if, it shows one of the listed faces,
+/// e.g. `d10r1`, `2d6ro<=2` or `d20r1r2`.
+///
+/// Rerolls happen on the first roll of a dice, before it may explode.
+#[derive(Clone, PartialEq)]
+struct RerollRule {
+    on: Vec<Compare>,
+    once: bool,
+}
+
+/// Stop rerolling a dice after so many tries, whatever it shows.
+const MAX_REROLLS: usize = 100;
+
+impl RerollRule {
+    pub fn notation(&self) -> String {
+        let r = if self.once { "ro" } else { "r" };
+        self.on
+            .iter()
+            .map(|c| format!("{}{}", r, c.short_notation()))
+            .collect()
+    }
+
+    pub fn matches(&self, value: i32) -> bool {
+        self.on.iter().any(|c| c.matches(value))
+    }
+}
+
+impl ExplodeRule {
+    pub fn notation(&self) -> String {
+        format!(
+            "{}{}",
+            if self.compound { "!!" } else { "!" },
+            self.on.map(|c| c.notation()).unwrap_or_default()
+        )
+    }
+
+    fn explodes(&self, value: i32, sides: i32) -> bool {
+        self.on.map_or(value == sides, |c| c.matches(value))
+    }
+}
+
+/// Which dice of a group count towards the result, e.g. `4d6kh3` or `4d6dl1`.
+#[derive(Clone, Copy, PartialEq)]
+enum KeepRule {
+    KeepHighest(i32),
+    KeepLowest(i32),
+    DropHighest(i32),
+    DropLowest(i32),
+}
+
+impl KeepRule {
+    pub fn notation(&self) -> String {
+        match self {
+            KeepRule::KeepHighest(n) => format!("kh{}", n),
+            KeepRule::KeepLowest(n) => format!("kl{}", n),
+            KeepRule::DropHighest(n) => format!("dh{}", n),
+            KeepRule::DropLowest(n) => format!("dl{}", n),
+        }
+    }
+
+    /// Mark the dice that are not kept as dropped. Of equal values the earlier dice is dropped.
+    fn apply(&self, dice: &mut [DieRecord]) {
+        let count = dice.len() as i32;
+        let (drop_highest, drop) = match *self {
+            KeepRule::KeepHighest(n) => (false, count - n),
+            KeepRule::KeepLowest(n) => (true, count - n),
+            KeepRule::DropHighest(n) => (true, n),
+            KeepRule::DropLowest(n) => (false, n),
+        };
+
+        let mut order: Vec<usize> = (0..dice.len()).collect();
+        if drop_highest {
+            order.sort_by_key(|&i| std::cmp::Reverse(dice[i].value));
+        } else {
+            order.sort_by_key(|&i| dice[i].value);
+        }
+        for &i in order.iter().take(drop.clamp(0, count) as usize) {
+            dice[i].dropped = true;
+        }
+    }
+}
+
+/// Count the dice meeting a target instead of summing them, e.g. `8d10>=8`.
+///
+/// Faces matching `double` count as two successes (`8d10>=8db10`), faces matching
+/// `failure` cancel one (`8d10>=8f1`) and `glitch` enables the Shadowrun glitch check.
+#[derive(Clone, Copy, PartialEq)]
+struct PoolRule {
+    success: Compare,
+    double: Option<Compare>,
+    failure: Option<Compare>,
+    glitch: bool,
+}
+
+impl PoolRule {
+    pub fn new(success: Compare) -> PoolRule {
+        PoolRule {
+            success,
+            double: None,
+            failure: None,
+            glitch: false,
+        }
+    }
+
+    pub fn notation(&self) -> String {
+        let mut s = self.success.notation();
+        if let Some(double) = self.double {
+            s.push_str("db");
+            s.push_str(&double.short_notation());
+        }
+        if let Some(failure) = self.failure {
+            s.push('f');
+            s.push_str(&failure.short_notation());
+        }
+        if self.glitch {
+            s.push('g');
+        }
+        s
+    }
+
+    /// Net successes of a single face.
+    fn score(&self, face: i32) -> i32 {
+        let success = match self.double {
+            Some(double) if double.matches(face) => 2,
+            _ if self.success.matches(face) => 1,
+            _ => 0,
+        };
+        let failure = match self.failure {
+            Some(failure) if failure.matches(face) => 1,
+            _ => 0,
+        };
+        success - failure
+    }
+}
+
+/// Something went badly wrong with a pool roll, worst last.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PoolMishap {
+    /// Shadowrun: more than half of the dice show 1.
+    Glitch,
+    /// World of Darkness: no success but at least one failure face.
+    Botch,
+    /// Shadowrun: a glitch without any success.
+    CriticalGlitch,
+}
+
+impl PoolMishap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolMishap::Glitch => "glitch",
+            PoolMishap::Botch => "botch",
+            PoolMishap::CriticalGlitch => "critical glitch",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct DiceGroup {
+    count: i32,
+    sides: i32,
+    /// The group is subtracted from the total instead of added.
+    negative: bool,
+    keep: Option<KeepRule>,
+    explode: Option<ExplodeRule>,
+    reroll: Option<RerollRule>,
+    pool: Option<PoolRule>,
+}
+
+impl DiceGroup {
+    pub const fn new(count: i32, sides: i32) -> DiceGroup {
+        DiceGroup {
+            count,
+            sides,
+            negative: false,
+            keep: None,
+            explode: None,
+            reroll: None,
+            pool: None,
+        }
+    }
+
+    /// Notation of the modifiers following `XdY`, e.g. `kh3`.
+    pub fn modifiers(&self) -> String {
+        let mut s = String::new();
+        if let Some(reroll) = &self.reroll {
+            s.push_str(&reroll.notation());
+        }
+        if let Some(explode) = self.explode {
+            s.push_str(&explode.notation());
+        }
+        if let Some(keep) = self.keep {
+            s.push_str(&keep.notation());
+        }
+        if let Some(pool) = self.pool {
+            s.push_str(&pool.notation());
+        }
+        s
+    }
+
+    /// e.g. `4D6dl1`, without the sign.
+    pub fn notation(&self) -> String {
+        format!("{}D{}{}", self.count, self.sides, self.modifiers())
+    }
+
+    pub fn name(&self) -> String {
+        format!(
+            "{}D{}{}",
+            if self.negative { "-" } else { "" },
+            self.sides,
+            self.modifiers()
+        )
+    }
+
+    pub fn roll(&self, rd: &mut impl Rng) -> Vec<DieRecord> {
+        let mut dice: Vec<DieRecord> = (0..self.count)
+            .map(|_| {
+                let mut die = DieRecord::new(rd.gen_range(1..=self.sides));
+                if let Some(reroll) = &self.reroll {
+                    while reroll.matches(die.value)
+                        && die.rerolled.len() < if reroll.once { 1 } else { MAX_REROLLS }
+                    {
+                        die.reroll(rd.gen_range(1..=self.sides));
+                    }
+                }
+                if let Some(explode) = self.explode {
+                    let mut last = die.value;
+                    while explode.explodes(last, self.sides) && die.rolls.len() <= MAX_EXPLOSIONS {
+                        last = rd.gen_range(1..=self.sides);
+                        die.push_roll(last);
+                    }
+                }
+                die
+            })
+            .collect();
+        if let Some(keep) = self.keep {
+            keep.apply(&mut dice);
+        }
+        dice
+    }
+
+    /// The faces a pool looks at: every roll of an exploding chain on its own, but
+    /// a compounded chain as a whole.
+    fn pool_faces<'a>(&self, dice: &'a [DieRecord]) -> impl Iterator<Item = i32> + 'a {
+        let compound = matches!(self.explode, Some(e) if e.compound);
+        dice.iter().filter(|d| !d.dropped).flat_map(move |d| {
+            if compound {
+                vec![d.value]
+            } else {
+                d.rolls.clone()
+            }
+        })
+    }
+
+    pub fn mishap(&self, dice: &[DieRecord]) -> Option<PoolMishap> {
+        let pool = self.pool?;
+        let faces: Vec<i32> = self.pool_faces(dice).collect();
+        let successes = faces.iter().filter(|&&f| pool.score(f) > 0).count();
+
+        if pool.glitch && faces.iter().filter(|&&f| f == 1).count() * 2 > faces.len() {
+            return Some(if successes == 0 {
+                PoolMishap::CriticalGlitch
+            } else {
+                PoolMishap::Glitch
+            });
+        }
+        match pool.failure {
+            Some(failure) if successes == 0 && faces.iter().any(|&f| failure.matches(f)) => {
+                Some(PoolMishap::Botch)
+            }
+            _ => None,
+        }
+    }
+
+    /// Lowest and highest subtotal, not counting explosions.
+    pub fn range(&self) -> (i32, i32) {
+        let kept = match self.keep {
+            Some(KeepRule::KeepHighest(n) | KeepRule::KeepLowest(n)) => n,
+            Some(KeepRule::DropHighest(n) | KeepRule::DropLowest(n)) => self.count - n,
+            None => self.count,
+        };
+        let (min, max) = match self.pool {
+            Some(pool) => (
+                if pool.failure.is_some() { -kept } else { 0 },
+                if pool.double.is_some() {
+                    2 * kept
+                } else {
+                    kept
+                },
+            ),
+            None => (kept, kept * self.sides),
+        };
+        if self.negative {
+            (-max, -min)
+        } else {
+            (min, max)
+        }
+    }
+
+    /// Signed contribution of the rolled `dice` to the total.
+    pub fn subtotal(&self, dice: &[DieRecord]) -> i32 {
+        let sum: i32 = match self.pool {
+            Some(pool) => self.pool_faces(dice).map(|f| pool.score(f)).sum(),
+            None => dice.iter().filter(|d| !d.dropped).map(|d| d.value).sum(),
+        };
+        if self.negative {
+            -sum
+        } else {
+            sum
+        }
+    }
 }
 
 #[derive(Clone)]
-struct DicesState<const N: usize> {
-    dice_num: [i32; N],
+struct DicesState {
+    groups: Vec<DiceGroup>,
     constant: i32,
+    /// Arithmetic beyond a sum, e.g. `(2d6+6)*5`. The groups are then all positive and
+    /// the constant is 0, both being part of the formula.
+    formula: Option<expr::Expr>,
+    /// The total passes when it meets this, e.g. `1d20+5 >= 15`.
+    target: Option<Compare>,
+    /// Times the whole roll is made, e.g. 3 for `3#1d20+5`.
+    repeat: i32,
 }
 
-impl<const N: usize> DicesState<N> {
-    pub const fn new(state: [i32; N], constant: i32) -> DicesState<N> {
+impl DicesState {
+    pub const fn new(groups: Vec<DiceGroup>, constant: i32) -> DicesState {
         DicesState {
-            dice_num: state,
+            groups,
             constant,
+            formula: None,
+            target: None,
+            repeat: 1,
         }
     }
 
+    /// `count` dice of `sides` sides and nothing else, as used by the quick roll buttons.
+    pub fn single(count: i32, sides: i32) -> DicesState {
+        DicesState::new(vec![DiceGroup::new(count, sides)], 0)
+    }
+
     pub fn valid(&self) -> bool {
-        self.dice_num.iter().any(|&n| n != 0)
+        self.groups.iter().any(|g| g.count != 0)
+    }
+
+    /// The highest total this can roll, not counting explosions.
+    pub fn max_total(&self) -> i32 {
+        match &self.formula {
+            Some(formula) => formula.range(&|i| self.groups[i].range()).1.floor() as i32,
+            None => self.groups.iter().map(|g| g.range().1).sum::<i32>() + self.constant,
+        }
+    }
+
+    /// Every dice counts successes, so the total is a number of successes.
+    pub fn is_pool(&self) -> bool {
+        if self.formula.is_some() {
+            return false;
+        }
+        let mut dices = self.groups.iter().filter(|g| g.count != 0).peekable();
+        dices.peek().is_some() && dices.all(|g| g.pool.is_some())
     }
 
     pub fn gen_description(&self) -> String {
+        self.describe(&self.gen_total_description())
+    }
+
+    /// Without spaces in the total, e.g. `1D20+5 >= 15`.
+    pub fn gen_compact_description(&self) -> String {
+        self.describe(&self.gen_total_description().replace(' ', ""))
+    }
+
+    /// The repeats and the target around `total`.
+    fn describe(&self, total: &str) -> String {
+        let mut s = if self.repeat > 1 {
+            format!("{}#", self.repeat)
+        } else {
+            String::new()
+        };
+        s.push_str(total);
+        if let Some(target) = self.target {
+            s.push_str(&format!(" {} {}", target.op.symbol(), target.value));
+        }
+        s
+    }
+
+    fn gen_total_description(&self) -> String {
+        if let Some(formula) = &self.formula {
+            return formula.format(&|i| self.groups[i].notation());
+        }
         let mut s = String::new();
-        let mut plus = false;
-        const PLUS: &str = " + ";
-        for i in 0..N {
-            if self.dice_num[i] != 0 {
-                if plus {
-                    s.push_str(PLUS);
-                } else {
-                    plus = true;
-                }
-                s.push_str(&format!("{}D{}", self.dice_num[i], DICE_TYPE[i]));
+        let mut push_term = |negative: bool, term: String| {
+            match (s.is_empty(), negative) {
+                (true, false) => {}
+                (true, true) => s.push('-'),
+                (false, false) => s.push_str(" + "),
+                (false, true) => s.push_str(" - "),
             }
-        }
+            s.push_str(&term);
+        };
 
+        for g in self.groups.iter().filter(|g| g.count != 0) {
+            push_term(g.negative, g.notation());
+        }
         if self.constant != 0 {
-            if plus {
-                s.push_str(PLUS)
-            };
-            s.push_str(&format!("{}", self.constant));
+            push_term(self.constant < 0, self.constant.abs().to_string());
         }
         s
     }
-}
 
-impl<const N: usize> DicesState<N> {
-    pub fn roll(&self, rd: &mut rand::rngs::ThreadRng) -> Box<RollRecord<N>> {
-        let mut records: [Vec<i32>; N] = {
-            let mut records_raw: [MaybeUninit<Vec<i32>>; N] =
-                unsafe { MaybeUninit::uninit().assume_init() };
-            for r in records_raw.iter_mut() {
-                r.write(Vec::new());
+    /// Build a state from dice notation such as `3d6+2`, `1d20+1d4-1` or `(2d6+6)*5`.
+    ///
+    /// Every dice term becomes its own group, so `1d6+2d6` keeps two groups. A plain sum
+    /// needs no formula.
+    pub fn from_notation(expression: &str) -> Result<DicesState, ParseError> {
+        let notation::Notation {
+            mut groups,
+            expr,
+            target,
+            repeat,
+        } = notation::parse(expression)?;
+        let mut negative = vec![false; groups.len()];
+        let mut constant = 0;
+        let mut state = if expr.flatten(false, &mut negative, &mut constant) {
+            for (g, negative) in groups.iter_mut().zip(negative) {
+                g.negative = negative;
+            }
+            DicesState::new(groups, constant)
+        } else {
+            DicesState {
+                groups,
+                constant: 0,
+                formula: Some(expr),
+                target: None,
+                repeat: 1,
             }
+        };
+        state.target = target;
+        state.repeat = repeat;
+        Ok(state)
+    }
+
+    pub fn subtotals(&self, records: &[Vec<DieRecord>]) -> Vec<i32> {
+        self.groups
+            .iter()
+            .zip(records)
+            .map(|(g, dice)| g.subtotal(dice))
+            .collect()
+    }
+}
+
+impl DicesState {
+    pub fn roll(&self, rd: &mut impl Rng) -> Box<RollRecord> {
+        if self.repeat > 1 {
+            let single = DicesState {
+                repeat: 1,
+                ..self.clone()
+            };
+            let repeats: Vec<RollRecord> = (0..self.repeat).map(|_| *single.roll(rd)).collect();
+            return self.record_of_repeats(repeats);
+        }
 
-            unsafe { std::mem::transmute_copy(&records_raw) }
+        let records: Vec<Vec<DieRecord>> = self.groups.iter().map(|g| g.roll(rd)).collect();
+        self.record_of(records)
+    }
+
+    /// The record of a single roll whose dice gave `records`.
+    pub fn record_of(&self, records: Vec<Vec<DieRecord>>) -> Box<RollRecord> {
+        let subtotals = self.subtotals(&records);
+        let total = match &self.formula {
+            Some(formula) => formula.total(&subtotals),
+            None => subtotals.iter().sum::<i32>() + self.constant,
         };
 
-        let mut sum = self.constant;
+        RollRecord::new(self.clone(), records, total)
+    }
 
-        for i in 0..N {
-            records[i].resize_with(self.dice_num[i] as usize, || {
-                let r = rd.gen_range(1..=DICE_TYPE[i]);
-                sum += r;
-                r
-            });
+    /// The record of `N#...` made of the record of every roll.
+    pub fn record_of_repeats(&self, repeats: Vec<RollRecord>) -> Box<RollRecord> {
+        let total = repeats.iter().map(|r| r.total).sum();
+        let mut record = RollRecord::new(self.clone(), Vec::new(), total);
+        record.repeats = repeats;
+        record
+    }
+}
+
+/// D&D 5e advantage and disadvantage: the d20 is rolled twice, keeping the higher or lower.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum D20Mode {
+    Normal,
+    Advantage,
+    Disadvantage,
+}
+
+impl D20Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            D20Mode::Normal => "normal",
+            D20Mode::Advantage => "adv",
+            D20Mode::Disadvantage => "dis",
         }
+    }
 
-        Box::new(RollRecord {
-            records,
-            state: self.clone(),
-            time: chrono::Local::now().time(),
-            description: self.gen_description(),
-            total: sum,
-        })
+    /// Every added single D20 without keep or pool rules becomes 2D20 keeping one,
+    /// so the modifiers still apply once. `None` if there is no such D20.
+    fn apply(&self, state: &DicesState) -> Option<DicesState> {
+        let keep = match self {
+            D20Mode::Normal => return None,
+            D20Mode::Advantage => KeepRule::KeepHighest(1),
+            D20Mode::Disadvantage => KeepRule::KeepLowest(1),
+        };
+        let mut state = state.clone();
+        let mut applied = false;
+        for g in state.groups.iter_mut() {
+            if g.sides == 20 && g.count == 1 && !g.negative && g.keep.is_none() && g.pool.is_none()
+            {
+                g.count = 2;
+                g.keep = Some(keep);
+                applied = true;
+            }
+        }
+        if applied {
+            Some(state)
+        } else {
+            None
+        }
     }
 }
 
 const RECORD_MAX_NUM: usize = 1024;
 
-struct RecordWindow<const N: usize> {
-    record: Box<RollRecord<N>>,
+struct RecordWindow {
+    record: Box<RollRecord>,
     should_open: bool,
 }
 
-impl<const N: usize> RecordWindow<N> {
-    pub fn new(record: Box<RollRecord<N>>) -> RecordWindow<N> {
+impl RecordWindow {
+    pub fn new(record: Box<RollRecord>) -> RecordWindow {
         RecordWindow {
             record,
             should_open: true,
         }
     }
 
-    pub fn show(record: &RollRecord<N>, should_open: &mut bool, ctx: &egui::CtxRef) {
+    pub fn show(record: &RollRecord, should_open: &mut bool, ctx: &egui::CtxRef) {
         egui::Window::new(egui::RichText::new(
-            record.time.format("[%H:%M:%S]  => ").to_string() + &record.total.to_string(),
+            record.time.format("[%H:%M:%S]  => ").to_string() + &record.result_text(),
         ))
         .collapsible(true)
         .vscroll(true)
@@ -134,53 +811,374 @@ impl<const N: usize> RecordWindow<N> {
             egui::Grid::new(record as *const _)
                 .striped(true)
                 .show(ui, |ui| {
-                    for i in 0..N {
-                        if record.state.dice_num[i] != 0 {
-                            ui.strong(format!("D{}", DICE_TYPE[i]));
-                            record.records[i].iter().for_each(|n| {
-                                ui.label(n.to_string());
-                            });
+                    if record.repeats.is_empty() {
+                        RecordWindow::show_dice_rows(ui, record);
+                    }
+                    for (i, r) in record.repeats.iter().enumerate() {
+                        ui.heading(format!("#{}", i + 1));
+                        ui.strong(r.result_text());
+                        if let Some(dist) = &r.distribution {
+                            dist.show_badge(ui, r.total);
+                        }
+                        if let Some((pass, margin)) = r.outcome() {
+                            RecordWindow::show_outcome(ui, pass, margin);
+                        }
+                        ui.end_row();
+                        RecordWindow::show_dice_rows(ui, r);
+                    }
+
+                    if let Some(percentile) = &record.percentile {
+                        ui.strong("Units");
+                        ui.label(percentile.units.to_string());
+                        ui.end_row();
+
+                        ui.strong("Tens");
+                        for (i, tens) in percentile.tens.iter().enumerate() {
+                            let text = egui::RichText::new(format!("{:02}", tens * 10));
+                            if i == percentile.chosen {
+                                ui.label(text.strong());
+                            } else {
+                                ui.label(text.strikethrough().color(egui::Color32::GRAY))
+                                    .on_hover_text("Not selected");
+                            }
+                        }
+                        ui.end_row();
+                    }
+
+                    if let Some(check) = &record.check {
+                        ui.strong("Skill");
+                        ui.label(check.skill.to_string());
+                        ui.label(
+                            egui::RichText::new(check.level.name())
+                                .strong()
+                                .color(check.level.color()),
+                        );
+                        ui.end_row();
+                    }
+
+                    if let Some(abilities) = &record.abilities {
+                        for roll in &abilities.rolls {
+                            ui.strong(format!(
+                                "{} ({})",
+                                roll.total,
+                                dnd::format_modifier(dnd::modifier(roll.total))
+                            ));
+                            roll.records
+                                .iter()
+                                .flatten()
+                                .for_each(|d| RecordWindow::show_die(ui, d));
+                            if roll.state.constant != 0 {
+                                ui.label(format!("{:+}", roll.state.constant));
+                            }
+                            ui.end_row();
+                        }
+
+                        ui.strong("Modifiers");
+                        ui.label(dnd::format_modifier(abilities.total_modifier()));
+                        ui.end_row();
+
+                        ui.strong("Point buy");
+                        let scores: Vec<i32> = abilities.scores().collect();
+                        dnd::show_point_buy(ui, &scores);
+                        ui.end_row();
+
+                        if abilities.discarded != 0 {
+                            ui.strong("Rerolled");
+                            ui.label(format!("{} arrays", abilities.discarded))
+                                .on_hover_text("Thrown away for a low total modifier.");
+                            ui.end_row();
+                        }
+                    }
+
+                    if let Some(sanity) = &record.sanity {
+                        ui.strong("SAN");
+                        ui.label(format!("{} => {}", sanity.san, sanity.new_san()));
+                        ui.end_row();
+
+                        ui.strong("Loss");
+                        match &sanity.loss_roll {
+                            Some(loss) => {
+                                ui.label(format!("{} ({})", loss.total.max(0), loss.description));
+                                ui.end_row();
+                                RecordWindow::show_dice_rows(ui, loss);
+                            }
+                            None => {
+                                ui.label(format!(
+                                    "{} (max of {} on a fumble)",
+                                    sanity.loss, sanity.failure_loss
+                                ));
+                                ui.end_row();
+                            }
+                        }
+
+                        if sanity.needs_int_roll() {
+                            ui.label(
+                                egui::RichText::new("Lost 5 or more, roll INT!")
+                                    .strong()
+                                    .color(egui::Color32::RED),
+                            )
+                            .on_hover_text("Temporary insanity on a successful INT roll.");
                             ui.end_row();
                         }
                     }
-                    if record.state.constant != 0 {
-                        ui.strong("Const");
-                        ui.label(record.state.constant.to_string());
+
+                    if let (Some(target), Some((pass, margin))) =
+                        (record.state.target, record.outcome())
+                    {
+                        ui.strong("Target");
+                        ui.label(format!("{} {}", target.op.symbol(), target.value));
+                        RecordWindow::show_outcome(ui, pass, margin);
                         ui.end_row();
                     }
 
                     ui.heading("Result:");
                     ui.label(
-                        egui::RichText::new(record.total.to_string())
+                        egui::RichText::new(record.result_text())
                             .heading()
                             .color(egui::Color32::RED),
                     );
                 });
+
+            if let Some(fair) = &record.fair {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.strong("Fair");
+                    ui.label(format!(
+                        "#{} of {}",
+                        fair.nonce,
+                        fair.commitment.get(..16).unwrap_or_default()
+                    ))
+                    .on_hover_text(format!(
+                        "Commitment {}\nPlayer seed \"{}\"",
+                        fair.commitment, fair.player_seed
+                    ));
+                });
+            }
+
+            if let Some(replay) = record.replay {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.strong("Seed");
+                    ui.label(format!("{} @ {}", replay.seed, replay.word_pos))
+                        .on_hover_text(
+                            "ChaCha seed and the position in its stream the roll started from.",
+                        );
+                });
+                egui::CollapsingHeader::new("Replay")
+                    .id_source((record as *const RollRecord, "replay"))
+                    .show(ui, |ui| match record.replayed() {
+                        Some(replayed) => {
+                            ui.horizontal(|ui| {
+                                ui.strong(replayed.result_text());
+                                if replayed.result_text() == record.result_text() {
+                                    ui.label(
+                                        egui::RichText::new("✔ same").color(egui::Color32::GREEN),
+                                    );
+                                } else {
+                                    ui.label(
+                                        egui::RichText::new("✖ differs").color(egui::Color32::RED),
+                                    );
+                                }
+                            });
+                        }
+                        None => {
+                            ui.label(
+                                egui::RichText::new("The roll could not be made again.").weak(),
+                            );
+                        }
+                    });
+            }
+
+            if let Some(dist) = &record.distribution {
+                ui.separator();
+                let markers: Vec<i32> = if record.repeats.is_empty() {
+                    vec![record.total]
+                } else {
+                    record.repeats.iter().map(|r| r.total).collect()
+                };
+                dist.show(ui, (record as *const RollRecord, "chart"), &markers);
+            }
+        });
+    }
+
+    fn show_outcome(ui: &mut egui::Ui, pass: bool, margin: i32) {
+        let (outcome, color) = if pass {
+            ("Pass", egui::Color32::DARK_GREEN)
+        } else {
+            ("Fail", egui::Color32::RED)
+        };
+        ui.label(egui::RichText::new(outcome).strong().color(color));
+        ui.label(format!("margin {}", margin));
+    }
+
+    /// One row for every group of dice, then the constant, or the math of the formula.
+    fn show_dice_rows(ui: &mut egui::Ui, record: &RollRecord) {
+        for (g, values) in record.state.groups.iter().zip(&record.records) {
+            if g.count != 0 {
+                if g.negative {
+                    ui.strong(egui::RichText::new(g.name()).color(egui::Color32::DARK_RED))
+                        .on_hover_text("Subtracted from the result.");
+                } else {
+                    ui.strong(g.name());
+                }
+                values.iter().for_each(|d| RecordWindow::show_die(ui, d));
+                ui.end_row();
+            }
+        }
+        if record.state.constant != 0 {
+            ui.strong("Const");
+            ui.label(record.state.constant.to_string());
+            ui.end_row();
+        }
+
+        if let Some(formula) = &record.state.formula {
+            let subtotals = record.state.subtotals(&record.records);
+            ui.strong("Formula");
+            ui.label(formula.format(&|i| subtotals[i].to_string()));
+            ui.end_row();
+
+            let mut steps = Vec::new();
+            let value = formula.eval(&subtotals, &mut steps);
+            if value.fract() != 0.0 {
+                steps.push(format!(
+                    "{} rounded down = {}",
+                    expr::format_value(value),
+                    record.total
+                ));
+            }
+            for (i, step) in steps.iter().enumerate() {
+                if i == 0 {
+                    ui.strong("Math");
+                } else {
+                    ui.label("");
+                }
+                ui.label(step);
+                ui.end_row();
+            }
+        }
+    }
+
+    fn show_die(ui: &mut egui::Ui, die: &DieRecord) {
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 2.0;
+            for r in die.rerolled.iter() {
+                ui.label(
+                    egui::RichText::new(r.to_string())
+                        .strikethrough()
+                        .color(egui::Color32::GRAY),
+                )
+                .on_hover_text("Rerolled");
+            }
+
+            let text = if die.rolls.len() > 1 {
+                let chain: Vec<String> = die.rolls.iter().map(|r| r.to_string()).collect();
+                format!("{} ({})", die.value, chain.join("+"))
+            } else {
+                die.value.to_string()
+            };
+            if die.dropped {
+                ui.label(
+                    egui::RichText::new(text)
+                        .strikethrough()
+                        .color(egui::Color32::GRAY),
+                )
+                .on_hover_text("Dropped");
+            } else {
+                ui.label(text);
+            }
         });
     }
 }
 
-struct RecordLine<const N: usize> {
-    record: Box<RollRecord<N>>,
+struct RecordLine {
+    record: Box<RollRecord>,
     is_detail_show: bool,
+    /// Picked for the export.
+    is_selected: bool,
 }
-impl<const N: usize> RecordLine<N> {
-    pub fn new(record: Box<RollRecord<N>>) -> RecordLine<N> {
+impl RecordLine {
+    pub fn new(record: Box<RollRecord>) -> RecordLine {
         RecordLine {
             record,
             is_detail_show: false,
+            is_selected: false,
         }
     }
 }
 
+/// Distributions kept for reuse by rolls of the same dice.
+const DISTRIBUTION_CACHE_SIZE: usize = 64;
+
 #[derive(Default)]
-struct RecordManager<const N: usize> {
-    table: std::collections::VecDeque<RecordLine<N>>,
-    remain_windows: std::collections::VecDeque<RecordWindow<N>>,
+struct RecordManager {
+    table: std::collections::VecDeque<RecordLine>,
+    remain_windows: std::collections::VecDeque<RecordWindow>,
+    /// By the description of the rolled state.
+    distributions: std::collections::HashMap<String, Option<std::rc::Rc<stats::Distribution>>>,
+    history: history::History,
+    /// Name of the session being played, its rolls are saved to a file of their own.
+    session: Option<String>,
+    export: export::ExportWindow,
+    sessions: session::SessionWindow,
+    filter: filter::RecordFilter,
 }
 
-impl<const N: usize> RecordManager<N> {
-    pub fn add_record(&mut self, record: Box<RollRecord<N>>) {
+impl RecordManager {
+    /// With the records saved by the last runs.
+    pub fn load() -> RecordManager {
+        let mut manager = RecordManager::default();
+        manager.open_log(&history::default_path(), None);
+        manager
+    }
+
+    /// Goes on saving the rolls to the file at `path`, showing the last ones in it.
+    fn open_log(&mut self, path: &std::path::Path, name: Option<String>) {
+        let (history, records) = history::History::open(path, name.as_deref(), RECORD_MAX_NUM);
+        for line in std::mem::take(&mut self.table) {
+            if line.is_detail_show {
+                self.remain_windows
+                    .push_back(RecordWindow::new(line.record));
+            }
+        }
+        self.history = history;
+        self.session = name;
+        for record in records {
+            self.push_record(Box::new(record));
+        }
+    }
+
+    /// The distribution of a roll of `state`, shared with the other rolls of it.
+    fn distribution(&mut self, state: &DicesState) -> Option<std::rc::Rc<stats::Distribution>> {
+        let single = DicesState {
+            repeat: 1,
+            ..state.clone()
+        };
+        let key = single.gen_description();
+        if !self.distributions.contains_key(&key) {
+            if self.distributions.len() >= DISTRIBUTION_CACHE_SIZE {
+                self.distributions.clear();
+            }
+            let dist = stats::Distribution::of(&single).map(std::rc::Rc::new);
+            self.distributions.insert(key.clone(), dist);
+        }
+        self.distributions[&key].clone()
+    }
+
+    /// Saves the record to the history first.
+    pub fn add_record(&mut self, record: Box<RollRecord>) {
+        self.history.append(&record);
+        self.push_record(record);
+    }
+
+    fn push_record(&mut self, mut record: Box<RollRecord>) {
+        // CoC percentile rolls with bonus dice are not a plain D100.
+        if record.state.valid() && record.percentile.is_none() {
+            record.distribution = self.distribution(&record.state);
+            for r in record.repeats.iter_mut() {
+                r.distribution = record.distribution.clone();
+            }
+        }
+
         if self.table.len() >= RECORD_MAX_NUM {
             let front = self.table.pop_front().unwrap();
             if front.is_detail_show {
@@ -192,16 +1190,35 @@ impl<const N: usize> RecordManager<N> {
     }
 
     pub fn update(&mut self, ctx: &egui::CtxRef) {
+        self.export.update(&mut self.table, ctx);
+        let current = self.session.as_ref().map(|_| self.history.path());
+        match self.sessions.update(current, ctx) {
+            Some(session::SessionAction::Open { path, name }) => self.open_log(&path, Some(name)),
+            Some(session::SessionAction::End) => self.open_log(&history::default_path(), None),
+            None => {}
+        }
+
         egui::SidePanel::right("record_panel")
             .default_width(460.0)
             .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    match &self.session {
+                        Some(name) => ui.strong(format!("Session: {}", name)),
+                        None => ui.label(egui::RichText::new("No session").weak()),
+                    };
+                    if ui.small_button("sessions").clicked() {
+                        self.sessions.open();
+                    }
+                });
                 ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
                     ui.set_max_height(ui.available_height() - 30.0);
                     ui.add_space(4.0);
+                    let criteria = self.filter.show(ui, &self.table);
+                    ui.separator();
                     egui::ScrollArea::vertical()
                         .stick_to_bottom()
                         .show(ui, |ui| {
-                            self.show_record_table(ui, ctx);
+                            self.show_record_table(ui, ctx, &criteria);
                         });
                 });
 
@@ -235,15 +1252,30 @@ impl<const N: usize> RecordManager<N> {
                     response.on_hover_ui(|ui: &mut egui::Ui| {
                         ui.label("Single-Click to remain only one record.");
                         ui.label("Double-Click to remove all.");
+                        ui.label("The history saved on the disk is kept.");
                     });
 
+                    let export = ui.add(egui::Button::new(egui::RichText::new("export").strong()));
+                    if export.clicked() {
+                        self.export.is_show = true;
+                    }
+
+                    if let Some(e) = &self.history.error {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+
                     ui.separator();
                 });
             });
     }
 
-    fn show_record_table(&mut self, ui: &mut egui::Ui, ctx: &egui::CtxRef) {
-        let show_check_box = |ui: &mut egui::Ui, line: &mut RecordLine<N>| {
+    fn show_record_table(
+        &mut self,
+        ui: &mut egui::Ui,
+        ctx: &egui::CtxRef,
+        criteria: &filter::Criteria,
+    ) {
+        let show_check_box = |ui: &mut egui::Ui, line: &mut RecordLine| {
             let check_box = egui::Checkbox::new(&mut line.is_detail_show, "");
             let record = &line.record;
             let tooltip = |ui: &mut egui::Ui| {
@@ -255,6 +1287,57 @@ impl<const N: usize> RecordManager<N> {
             }
         };
 
+        let show_result = |ui: &mut egui::Ui, record: &RollRecord, color: egui::Color32| {
+            ui.horizontal(|ui| {
+                ui.heading(
+                    egui::RichText::new(record.result_text())
+                        .color(color)
+                        .text_style(egui::TextStyle::Monospace),
+                );
+                if let Some(check) = &record.check {
+                    ui.label(
+                        egui::RichText::new(check.level.name())
+                            .strong()
+                            .color(check.level.color()),
+                    );
+                }
+                if let Some((pass, margin)) = record.outcome() {
+                    let (mark, color) = if pass {
+                        ("✔", egui::Color32::DARK_GREEN)
+                    } else {
+                        ("✖", egui::Color32::RED)
+                    };
+                    ui.label(egui::RichText::new(mark).strong().color(color))
+                        .on_hover_text(format!("margin {}", margin));
+                }
+                if let (Some(dist), true) = (&record.distribution, record.repeats.is_empty()) {
+                    dist.show_badge(ui, record.total);
+                }
+                if record.state.target.is_some() && !record.repeats.is_empty() {
+                    let passes = record
+                        .repeats
+                        .iter()
+                        .filter(|r| r.outcome().unwrap().0)
+                        .count();
+                    let (mark, color) = if passes > 0 {
+                        ("✔", egui::Color32::DARK_GREEN)
+                    } else {
+                        ("✖", egui::Color32::RED)
+                    };
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{}/{} {}",
+                            passes,
+                            record.repeats.len(),
+                            mark
+                        ))
+                        .strong()
+                        .color(color),
+                    );
+                }
+            });
+        };
+
         egui::Grid::new("record_table")
             .min_col_width(50.0)
             .striped(true)
@@ -269,30 +1352,35 @@ impl<const N: usize> RecordManager<N> {
                 let last = self.table.pop_back();
                 for line in self.table.iter_mut() {
                     let record = &line.record;
+                    if !criteria.matches(record) {
+                        if line.is_detail_show {
+                            RecordWindow::show(record, &mut line.is_detail_show, ctx);
+                        }
+                        continue;
+                    }
                     ui.strong(record.time.format("%H:%M:%S").to_string());
                     ui.label(&record.description);
                     ui.add_space(10.0);
-                    ui.heading(
-                        egui::RichText::new(record.total.to_string())
-                            .color(egui::Color32::DARK_GREEN)
-                            .text_style(egui::TextStyle::Monospace),
-                    );
+                    show_result(ui, record, egui::Color32::DARK_GREEN);
                     show_check_box(ui, line);
                     ui.end_row();
                 }
                 if let Some(mut line) = last {
                     let record = &line.record;
+                    if !criteria.matches(record) {
+                        if line.is_detail_show {
+                            RecordWindow::show(record, &mut line.is_detail_show, ctx);
+                        }
+                        self.table.push_back(line);
+                        return;
+                    }
                     ui.strong(
                         egui::RichText::new(record.time.format("%H:%M:%S").to_string())
                             .color(egui::Color32::RED),
                     );
                     ui.strong(egui::RichText::new(&record.description).color(egui::Color32::RED));
                     ui.add_space(10.0);
-                    ui.heading(
-                        egui::RichText::new(record.total.to_string())
-                            .color(egui::Color32::DARK_RED)
-                            .text_style(egui::TextStyle::Monospace),
-                    );
+                    show_result(ui, record, egui::Color32::DARK_RED);
                     show_check_box(ui, &mut line);
                     self.table.push_back(line);
                 }
@@ -306,25 +1394,57 @@ impl<const N: usize> RecordManager<N> {
     }
 }
 
-struct DiceFeature<const N: usize> {
-    state: DicesState<N>,
-    records: RecordManager<N>,
+struct DiceFeature {
+    state: DicesState,
+    records: RecordManager,
 
-    quick_roll: QuickRoll<N>,
+    quick_roll: QuickRoll,
+    coc: coc::CocWindow,
+    abilities: dnd::AbilityWindow,
+    odds: stats::OddsWindow,
+    luck: luck::LuckWindow,
+    rng: rng::RngWindow,
+    verify: fair::VerifyWindow,
 
     player: SoundPlayer,
 
-    rd: std::cell::RefCell<rand::rngs::ThreadRng>,
+    new_dice_sides: i32,
+
+    expression: String,
+    expression_error: Option<String>,
+    /// Given to the rolls of the panel, like the skill of a check.
+    label: String,
+
+    d20_mode: D20Mode,
+
+    rd: std::cell::RefCell<rng::DiceRng>,
 }
 
-impl<const N: usize> DiceFeature<N> {
-    pub fn new() -> DiceFeature<N> {
+impl DiceFeature {
+    pub fn new() -> DiceFeature {
         DiceFeature {
-            state: DicesState::new([0; N], 0),
-            records: RecordManager::default(),
+            state: DicesState::new(
+                DEFAULT_DICE_TYPE
+                    .iter()
+                    .map(|&sides| DiceGroup::new(0, sides))
+                    .collect(),
+                0,
+            ),
+            records: RecordManager::load(),
             quick_roll: QuickRoll::new(),
+            coc: coc::CocWindow::new(),
+            abilities: dnd::AbilityWindow::new(),
+            odds: stats::OddsWindow::new(),
+            luck: luck::LuckWindow::new(),
+            rng: rng::RngWindow::new(),
+            verify: fair::VerifyWindow::new(),
             player: SoundPlayer::new(),
-            rd: std::cell::RefCell::new(rand::thread_rng()),
+            new_dice_sides: 2,
+            expression: String::new(),
+            expression_error: None,
+            label: String::new(),
+            d20_mode: D20Mode::Normal,
+            rd: std::cell::RefCell::new(rng::DiceRng::new()),
         }
     }
 
@@ -335,32 +1455,70 @@ impl<const N: usize> DiceFeature<N> {
                 .color(egui::Color32::DARK_BLUE),
         );
         if ui.add_sized([80.0, 30.0], reset).clicked() {
-            self.state.dice_num = [0; N];
+            self.state.groups.iter_mut().for_each(|g| g.count = 0);
             self.state.constant = 0;
         }
 
         egui::ScrollArea::vertical()
             .stick_to_bottom()
-            .max_height(ui.available_height() - 80.0)
+            .max_height(ui.available_height() - 195.0)
             .show(ui, |ui| {
                 egui::Grid::new("Selections")
                     .striped(true)
                     .min_col_width(100.0)
                     .min_row_height(40.0)
                     .show(ui, |ui| {
-                        for i in 0..N {
-                            ui.heading(format!("{}D{}", self.state.dice_num[i], DICE_TYPE[i]));
-                            ui.add(
-                                egui::DragValue::new(&mut self.state.dice_num[i])
-                                    .clamp_range::<i32>(0..=100)
-                                    .speed(0.05),
-                            );
+                        let mut removed = None;
+                        for (i, g) in self.state.groups.iter_mut().enumerate() {
+                            ui.heading(format!(
+                                "{}{}D{}",
+                                if g.negative { "-" } else { "" },
+                                g.count,
+                                g.sides
+                            ));
+                            ui.horizontal(|ui| {
+                                let sign = if g.negative { "-" } else { "+" };
+                                if ui
+                                    .small_button(sign)
+                                    .on_hover_text("Add or subtract this dice.")
+                                    .clicked()
+                                {
+                                    g.negative = !g.negative;
+                                }
+                                ui.add(
+                                    egui::DragValue::new(&mut g.count)
+                                        .clamp_range::<i32>(0..=100)
+                                        .speed(0.05),
+                                );
+                                if ui.small_button("x").on_hover_text("Remove").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
                             ui.end_row();
                         }
+                        if let Some(i) = removed {
+                            self.state.groups.remove(i);
+                        }
+
+                        ui.add(
+                            egui::DragValue::new(&mut self.new_dice_sides)
+                                .clamp_range::<i32>(2..=1000)
+                                .prefix("D")
+                                .speed(0.1),
+                        );
+                        let sides = self.new_dice_sides;
+                        let exists = self.state.groups.iter().any(|g| g.sides == sides);
+                        let add = egui::Button::new(format!("add D{}", sides));
+                        if ui.add_enabled(!exists, add).clicked() {
+                            let at = self.state.groups.partition_point(|g| g.sides < sides);
+                            self.state.groups.insert(at, DiceGroup::new(0, sides));
+                        }
+                        ui.end_row();
+
                         ui.heading("Constant");
                         ui.add(
                             egui::DragValue::new(&mut self.state.constant)
-                                .clamp_range::<i32>(0..=100)
+                                .clamp_range::<i32>(-100..=100)
                                 .speed(0.05),
                         );
                         ui.end_row();
@@ -369,6 +1527,76 @@ impl<const N: usize> DiceFeature<N> {
         ui.separator();
     }
 
+    fn show_expression_input(&mut self, ui: &mut egui::Ui) {
+        if let Some(err) = &self.expression_error {
+            ui.label(egui::RichText::new(err).color(egui::Color32::RED));
+        }
+
+        let input = egui::TextEdit::singleline(&mut self.expression).hint_text("e.g. 1d20+1d4-1");
+        let tool_tip = |ui: &mut egui::Ui| {
+            ui.label("Type a dice expression and press Enter to roll.");
+        };
+        let response = ui.add_sized([200.0, 24.0], input).on_hover_ui(tool_tip);
+        if response.changed() {
+            self.expression_error = None;
+        }
+
+        if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+            match DicesState::from_notation(&self.expression) {
+                Ok(state) if state.valid() => {
+                    let record = self.roll(&state, self.expression.trim());
+                    self.player.play();
+                    self.records.add_record(record);
+                }
+                Ok(_) => self.expression_error = Some("There is no dice to roll.".to_string()),
+                Err(e) => self.expression_error = Some(e.to_string()),
+            }
+            response.request_focus();
+        }
+
+        let input = egui::TextEdit::singleline(&mut self.label).hint_text("label, e.g. Stealth");
+        ui.add_sized([200.0, 20.0], input)
+            .on_hover_text("Given to the next rolls, to find them with the filter.");
+    }
+
+    /// Rolls with the advantage option. When it applies, the description is the compact
+    /// notation of `state` followed by the option, e.g. `1D20+5 (adv)`. The label, if
+    /// any, goes before it.
+    fn roll(&self, state: &DicesState, description: &str) -> Box<RollRecord> {
+        let rd = &mut self.rd.borrow_mut();
+        let mut record = match self.d20_mode.apply(state) {
+            Some(advantage) => {
+                let mut record = rd.replayable(|rd| advantage.roll(rd));
+                record.description = format!(
+                    "{} ({})",
+                    state.gen_compact_description(),
+                    self.d20_mode.name()
+                );
+                record
+            }
+            None => {
+                let mut record = rd.replayable(|rd| state.roll(rd));
+                record.description = description.to_string();
+                record
+            }
+        };
+        let label = self.label.trim();
+        if !label.is_empty() {
+            record.description = format!("{} {}", label, record.description);
+            record.label = Some(label.to_string());
+        }
+        record
+    }
+
+    fn show_d20_mode_toggle(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for mode in [D20Mode::Normal, D20Mode::Advantage, D20Mode::Disadvantage] {
+                ui.selectable_value(&mut self.d20_mode, mode, mode.name())
+                    .on_hover_text("Roll a single D20 twice and keep the higher or lower.");
+            }
+        });
+    }
+
     pub fn update(&mut self, ctx: &egui::CtxRef) {
         self.quick_roll.update(
             &mut self.records,
@@ -376,6 +1604,24 @@ impl<const N: usize> DiceFeature<N> {
             &mut self.rd.borrow_mut(),
             ctx,
         );
+        self.coc.update(
+            &mut self.records,
+            &self.player,
+            &mut self.rd.borrow_mut(),
+            ctx,
+        );
+        self.abilities.update(
+            &mut self.records,
+            &self.player,
+            &mut self.rd.borrow_mut(),
+            ctx,
+        );
+        self.odds.update(&self.state, ctx);
+        self.luck.update(&self.records.table, ctx);
+        if let Some(revealed) = self.rng.update(&mut self.rd.borrow_mut(), ctx) {
+            self.verify.open(revealed, &self.records);
+        }
+        self.verify.update(&self.records, ctx);
 
         self.player.show_audio_control_window(ctx);
         self.player.show_err_window(ctx);
@@ -411,15 +1657,19 @@ impl<const N: usize> DiceFeature<N> {
                     };
                     let response = ui.add_sized([200.0, 50.0], roll).on_hover_ui(tool_tip);
                     if response.clicked_by(egui::PointerButton::Primary) && self.state.valid() {
-                        self.player.play(&mut self.rd.borrow_mut());
-                        self.records
-                            .add_record(self.state.roll(&mut self.rd.borrow_mut()));
+                        self.player.play();
+                        let record = self.roll(&self.state, &self.state.gen_description());
+                        self.records.add_record(record);
                     }
 
                     if response.clicked_by(egui::PointerButton::Secondary) && self.state.valid() {
-                        self.records
-                            .add_record(self.state.roll(&mut self.rd.borrow_mut()));
+                        let record = self.roll(&self.state, &self.state.gen_description());
+                        self.records.add_record(record);
                     }
+
+                    self.show_d20_mode_toggle(ui);
+                    ui.add_space(4.0);
+                    self.show_expression_input(ui);
                 });
             });
 
@@ -456,6 +1706,48 @@ impl<const N: usize> DiceFeature<N> {
                         self.player.is_control_window_show = true;
                     }
                 }
+
+                if !self.coc.is_show {
+                    let show = egui::Button::new(egui::RichText::new("CoC rolls").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.coc.is_show = true;
+                    }
+                }
+
+                if !self.abilities.is_show {
+                    let show = egui::Button::new(egui::RichText::new("ability scores").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.abilities.is_show = true;
+                    }
+                }
+
+                if !self.odds.is_show {
+                    let show = egui::Button::new(egui::RichText::new("odds").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.odds.is_show = true;
+                    }
+                }
+
+                if !self.luck.is_show {
+                    let show = egui::Button::new(egui::RichText::new("statistics").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.luck.is_show = true;
+                    }
+                }
+
+                if !self.rng.is_show {
+                    let show = egui::Button::new(egui::RichText::new("random source").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.rng.is_show = true;
+                    }
+                }
+
+                if !self.verify.is_show {
+                    let show = egui::Button::new(egui::RichText::new("verify rolls").strong());
+                    if ui.add_sized([100.0, 30.0], show).clicked() {
+                        self.verify.is_show = true;
+                    }
+                }
             });
         });
 
@@ -463,66 +1755,50 @@ impl<const N: usize> DiceFeature<N> {
     }
 }
 
-struct QuickRoll<const N: usize> {
+struct QuickRoll {
     is_show: bool,
 }
 
-impl<const N: usize> QuickRoll<N> {
-    pub fn new() -> QuickRoll<N> {
+impl QuickRoll {
+    pub fn new() -> QuickRoll {
         QuickRoll { is_show: true }
     }
 
-    const fn array_n(ori: &[i32]) -> [i32; N] {
-        let mut trans = [0; N];
-        trans[0] = ori[0];
-        trans[1] = ori[1];
-        trans[2] = ori[2];
-        trans[3] = ori[3];
-        trans[4] = ori[4];
-        trans
-    }
-
-    const fn gen_state(ori: &[i32]) -> DicesState<N> {
-        DicesState::new(QuickRoll::array_n(ori), 0)
-    }
-
-    const STATE_1D4: DicesState<N> = QuickRoll::gen_state(&[1, 0, 0, 0, 0]);
-    const STATE_3D4: DicesState<N> = QuickRoll::gen_state(&[3, 0, 0, 0, 0]);
-    const STATE_1D6: DicesState<N> = QuickRoll::gen_state(&[0, 1, 0, 0, 0]);
-    const STATE_3D6: DicesState<N> = QuickRoll::gen_state(&[0, 3, 0, 0, 0]);
-    const STATE_1D100: DicesState<N> = QuickRoll::gen_state(&[0, 0, 0, 0, 1]);
+    /// `(count, sides)` of every quick roll button.
+    const BUTTONS: [(i32, i32); 5] = [(1, 4), (3, 4), (1, 6), (3, 6), (1, 100)];
 
     pub fn update(
         &mut self,
-        records: &mut RecordManager<N>,
+        records: &mut RecordManager,
         player: &SoundPlayer,
-        rd: &mut rand::rngs::ThreadRng,
+        rd: &mut rng::DiceRng,
         ctx: &egui::CtxRef,
     ) {
         egui::Window::new("QuickRoll")
             .auto_sized()
             .open(&mut self.is_show)
             .show(ctx, |ui| {
-                let mut add_button = |name: &str, state: &DicesState<N>| {
+                let mut add_button = |name: &str, state: DicesState| {
                     let response = ui.add_sized(
                         egui::Vec2::new(70.0, 30.0),
                         egui::Button::new(egui::RichText::new(name).heading()),
                     );
                     if response.clicked_by(egui::PointerButton::Primary) {
-                        player.play(rd);
-                        records.add_record(state.roll(rd));
+                        player.play();
+                        records.add_record(rd.replayable(|rd| state.roll(rd)));
                     };
 
                     if response.clicked_by(egui::PointerButton::Secondary) {
-                        records.add_record(state.roll(rd));
+                        records.add_record(rd.replayable(|rd| state.roll(rd)));
                     }
                 };
 
-                add_button("1D4", &QuickRoll::STATE_1D4);
-                add_button("3D4", &QuickRoll::STATE_3D4);
-                add_button("1D6", &QuickRoll::STATE_1D6);
-                add_button("3D6", &QuickRoll::STATE_3D6);
-                add_button("1D100", &QuickRoll::STATE_1D100);
+                for (count, sides) in QuickRoll::BUTTONS {
+                    add_button(
+                        &format!("{}D{}", count, sides),
+                        DicesState::single(count, sides),
+                    );
+                }
             });
     }
 }
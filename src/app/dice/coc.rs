@@ -0,0 +1,603 @@
+//! Rolls of Call of Cthulhu 7th edition.
+
+use super::super::sound::SoundPlayer;
+use super::rng::DiceRng;
+use super::{DicesState, DieRecord, RecordManager, RollRecord};
+use eframe::egui;
+use rand::Rng;
+
+/// Bonus or penalty dice beyond this cancel out or are ignored.
+pub const MAX_BONUS_DICE: i32 = 2;
+
+/// A D100 rolled as one units die and one or more tens dice.
+#[derive(Clone)]
+pub struct PercentileRoll {
+    /// 0..=9
+    pub units: i32,
+    /// Tens digits 0..=9, one more than the bonus or penalty dice.
+    pub tens: Vec<i32>,
+    /// Index of the tens die that counts.
+    pub chosen: usize,
+    /// Bonus dice if positive, penalty dice if negative.
+    pub bonus: i32,
+}
+
+impl PercentileRoll {
+    /// `bonus` and `penalty` cancel each other, then at most 2 are rolled.
+    pub fn roll(bonus: i32, penalty: i32, rd: &mut impl Rng) -> PercentileRoll {
+        let bonus = (bonus - penalty).clamp(-MAX_BONUS_DICE, MAX_BONUS_DICE);
+        let units = rd.gen_range(0..10);
+        let tens: Vec<i32> = (0..=bonus.abs()).map(|_| rd.gen_range(0..10)).collect();
+        PercentileRoll::new(units, tens, bonus)
+    }
+
+    /// Keeps the best of `tens` with bonus dice, the worst with penalty dice.
+    pub fn new(units: i32, tens: Vec<i32>, bonus: i32) -> PercentileRoll {
+        let values = tens.iter().map(|&t| PercentileRoll::combine(t, units));
+        let chosen = if bonus >= 0 {
+            values.enumerate().min_by_key(|&(_, v)| v)
+        } else {
+            values.enumerate().max_by_key(|&(_, v)| v)
+        }
+        .map(|(i, _)| i)
+        .unwrap();
+
+        PercentileRoll {
+            units,
+            tens,
+            chosen,
+            bonus,
+        }
+    }
+
+    /// 00 and 0 make 100.
+    fn combine(tens: i32, units: i32) -> i32 {
+        match tens * 10 + units {
+            0 => 100,
+            v => v,
+        }
+    }
+
+    pub fn value(&self) -> i32 {
+        PercentileRoll::combine(self.tens[self.chosen], self.units)
+    }
+
+    /// e.g. ` (1 bonus)`, empty without bonus or penalty dice.
+    pub fn describe_bonus(&self) -> String {
+        match self.bonus {
+            0 => String::new(),
+            b if b > 0 => format!(" ({} bonus)", b),
+            b => format!(" ({} penalty)", -b),
+        }
+    }
+
+    pub fn into_record(self) -> Box<RollRecord> {
+        let value = self.value();
+        let mut record = RollRecord::new(
+            DicesState::single(1, 100),
+            vec![vec![DieRecord::new(value)]],
+            value,
+        );
+        record.description.push_str(&self.describe_bonus());
+        record.percentile = Some(self);
+        record
+    }
+}
+
+/// How well a percentile roll went against a skill.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SuccessLevel {
+    Fumble,
+    Failure,
+    Regular,
+    Hard,
+    Extreme,
+    Critical,
+}
+
+impl SuccessLevel {
+    /// The level of `roll` (1..=100) against `skill`, following the 7th edition rules:
+    /// 01 is a critical, and 100 is a fumble, or 96-100 when the skill is below 50.
+    pub fn of(roll: i32, skill: i32) -> SuccessLevel {
+        let fumble = if skill < 50 { 96 } else { 100 };
+        if roll == 1 {
+            SuccessLevel::Critical
+        } else if roll >= fumble {
+            SuccessLevel::Fumble
+        } else if roll <= skill / 5 {
+            SuccessLevel::Extreme
+        } else if roll <= skill / 2 {
+            SuccessLevel::Hard
+        } else if roll <= skill {
+            SuccessLevel::Regular
+        } else {
+            SuccessLevel::Failure
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SuccessLevel::Fumble => "Fumble",
+            SuccessLevel::Failure => "Failure",
+            SuccessLevel::Regular => "Regular",
+            SuccessLevel::Hard => "Hard",
+            SuccessLevel::Extreme => "Extreme",
+            SuccessLevel::Critical => "Critical",
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self >= SuccessLevel::Regular
+    }
+
+    pub fn color(&self) -> egui::Color32 {
+        match self {
+            SuccessLevel::Fumble => egui::Color32::from_rgb(160, 0, 160),
+            SuccessLevel::Failure => egui::Color32::GRAY,
+            SuccessLevel::Regular => egui::Color32::DARK_GREEN,
+            SuccessLevel::Hard => egui::Color32::from_rgb(0, 110, 200),
+            SuccessLevel::Extreme => egui::Color32::DARK_BLUE,
+            SuccessLevel::Critical => egui::Color32::from_rgb(220, 140, 0),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SkillCheck {
+    pub skill: i32,
+    pub level: SuccessLevel,
+}
+
+impl SkillCheck {
+    pub fn roll(skill: i32, bonus: i32, penalty: i32, rd: &mut impl Rng) -> Box<RollRecord> {
+        SkillCheck::record(skill, PercentileRoll::roll(bonus, penalty, rd))
+    }
+
+    pub fn record(skill: i32, percentile: PercentileRoll) -> Box<RollRecord> {
+        let mut record = percentile.into_record();
+        record.check = Some(SkillCheck {
+            skill,
+            level: SuccessLevel::of(record.total, skill),
+        });
+        record
+    }
+}
+
+/// A SAN roll followed by the loss, e.g. `SAN 1/1d6`.
+pub struct SanityCheck {
+    /// Sanity before the check.
+    pub san: i32,
+    pub success_loss: String,
+    /// Shown when a fumble loses its maximum.
+    pub failure_loss: String,
+    /// `None` after a fumble, which loses the maximum.
+    pub loss_roll: Option<Box<RollRecord>>,
+    pub loss: i32,
+}
+
+impl SanityCheck {
+    /// Rolls the check against `san`, then the loss of `success_loss` or `failure_loss`,
+    /// both in dice notation. Either may be a plain number.
+    pub fn roll(
+        san: i32,
+        success_loss: &str,
+        failure_loss: &str,
+        rd: &mut impl Rng,
+    ) -> Result<Box<RollRecord>, String> {
+        let losses = SanityCheck::parse_losses(success_loss, failure_loss)?;
+        let percentile = PercentileRoll::roll(0, 0, rd);
+        let level = SuccessLevel::of(percentile.value(), san);
+        let loss_roll = SanityCheck::loss_dice(level, &losses).map(|state| state.roll(rd));
+        SanityCheck::record(san, success_loss, failure_loss, percentile, loss_roll)
+    }
+
+    /// The states of `success_loss` and `failure_loss`.
+    pub fn parse_losses(
+        success_loss: &str,
+        failure_loss: &str,
+    ) -> Result<(DicesState, DicesState), String> {
+        let parse = |expression: &str| {
+            DicesState::from_notation(expression)
+                .map_err(|e| format!("{}: {}", expression.trim(), e))
+        };
+        Ok((parse(success_loss)?, parse(failure_loss)?))
+    }
+
+    /// What is rolled for the loss after a check of `level`, nothing after a fumble.
+    pub fn loss_dice(
+        level: SuccessLevel,
+        (on_success, on_failure): &(DicesState, DicesState),
+    ) -> Option<&DicesState> {
+        if level == SuccessLevel::Fumble {
+            None
+        } else if level.is_success() {
+            Some(on_success)
+        } else {
+            Some(on_failure)
+        }
+    }
+
+    /// The record of a check that gave `percentile`, followed by `loss_roll` unless it
+    /// was a fumble.
+    pub fn record(
+        san: i32,
+        success_loss: &str,
+        failure_loss: &str,
+        percentile: PercentileRoll,
+        loss_roll: Option<Box<RollRecord>>,
+    ) -> Result<Box<RollRecord>, String> {
+        let (_, on_failure) = SanityCheck::parse_losses(success_loss, failure_loss)?;
+        let mut record = percentile.into_record();
+        let level = SuccessLevel::of(record.total, san);
+        let loss = match &loss_roll {
+            Some(loss_roll) => loss_roll.total.max(0),
+            None => on_failure.max_total().max(0),
+        };
+
+        record.description = format!(
+            "SAN {} ({}/{})",
+            san,
+            success_loss.trim(),
+            failure_loss.trim()
+        );
+        record.label = Some("SAN".to_string());
+        record.check = Some(SkillCheck { skill: san, level });
+        record.sanity = Some(SanityCheck {
+            san,
+            success_loss: success_loss.trim().to_string(),
+            failure_loss: failure_loss.trim().to_string(),
+            loss_roll,
+            loss,
+        });
+        Ok(record)
+    }
+
+    pub fn new_san(&self) -> i32 {
+        (self.san - self.loss).max(0)
+    }
+
+    /// Losing 5 or more at once calls for an INT roll against temporary insanity.
+    pub fn needs_int_roll(&self) -> bool {
+        self.loss >= 5
+    }
+}
+
+/// Name and roll of every characteristic.
+const CHARACTERISTICS: [(&str, &str); 9] = [
+    ("STR", "3d6*5"),
+    ("CON", "3d6*5"),
+    ("SIZ", "(2d6+6)*5"),
+    ("DEX", "3d6*5"),
+    ("APP", "3d6*5"),
+    ("INT", "(2d6+6)*5"),
+    ("POW", "3d6*5"),
+    ("EDU", "(2d6+6)*5"),
+    ("Luck", "3d6*5"),
+];
+
+/// Characteristics of a new investigator, in the order of [`CHARACTERISTICS`].
+/// Derived values are before any age adjustment.
+pub struct Investigator {
+    pub values: [i32; 9],
+}
+
+impl Investigator {
+    const STR: usize = 0;
+    const CON: usize = 1;
+    const SIZ: usize = 2;
+    const DEX: usize = 3;
+    const POW: usize = 6;
+
+    /// Every roll is also logged into `records`.
+    pub fn roll(records: &mut RecordManager, rd: &mut DiceRng) -> Investigator {
+        let mut values = [0; 9];
+        for (&(name, notation), value) in CHARACTERISTICS.iter().zip(values.iter_mut()) {
+            let state = DicesState::from_notation(notation).unwrap();
+            let mut record = rd.replayable(|rd| state.roll(rd));
+            record.description = format!("{} {}", name, record.description);
+            record.label = Some(name.to_string());
+            *value = record.total;
+            records.add_record(record);
+        }
+        Investigator { values }
+    }
+
+    pub fn hit_points(&self) -> i32 {
+        (self.values[Investigator::CON] + self.values[Investigator::SIZ]) / 10
+    }
+
+    pub fn magic_points(&self) -> i32 {
+        self.values[Investigator::POW] / 5
+    }
+
+    pub fn sanity(&self) -> i32 {
+        self.values[Investigator::POW]
+    }
+
+    pub fn move_rate(&self) -> i32 {
+        let str = self.values[Investigator::STR];
+        let dex = self.values[Investigator::DEX];
+        let siz = self.values[Investigator::SIZ];
+        if str < siz && dex < siz {
+            7
+        } else if str > siz && dex > siz {
+            9
+        } else {
+            8
+        }
+    }
+
+    /// Build and damage bonus, both from STR + SIZ.
+    pub fn build(&self) -> (i32, String) {
+        match self.values[Investigator::STR] + self.values[Investigator::SIZ] {
+            0..=64 => (-2, String::from("-2")),
+            65..=84 => (-1, String::from("-1")),
+            85..=124 => (0, String::from("0")),
+            125..=164 => (1, String::from("+1D4")),
+            165..=204 => (2, String::from("+1D6")),
+            total => {
+                // One more of each for every 80 points beyond.
+                let extra = (total - 205) / 80;
+                (3 + extra, format!("+{}D6", 2 + extra))
+            }
+        }
+    }
+
+    fn show(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("coc_characteristics")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("Full");
+                ui.label("Half");
+                ui.label("Fifth");
+                ui.end_row();
+                for (&(name, ..), &value) in CHARACTERISTICS.iter().zip(&self.values) {
+                    ui.strong(name);
+                    ui.heading(value.to_string());
+                    ui.label((value / 2).to_string());
+                    ui.label((value / 5).to_string());
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        egui::Grid::new("coc_derived").striped(true).show(ui, |ui| {
+            let (build, damage_bonus) = self.build();
+            let derived = [
+                ("HP", self.hit_points().to_string()),
+                ("MP", self.magic_points().to_string()),
+                ("SAN", self.sanity().to_string()),
+                ("Move", self.move_rate().to_string()),
+                ("Build", build.to_string()),
+                ("Damage bonus", damage_bonus),
+            ];
+            for (name, value) in derived {
+                ui.strong(name);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+        ui.label(egui::RichText::new("Before age adjustments.").weak());
+    }
+}
+
+pub struct CocWindow {
+    pub is_show: bool,
+
+    skill_name: String,
+    skill: i32,
+    bonus: i32,
+    penalty: i32,
+
+    san: i32,
+    success_loss: String,
+    failure_loss: String,
+    sanity_error: Option<String>,
+
+    investigator: Option<Investigator>,
+    is_investigator_show: bool,
+}
+
+impl CocWindow {
+    pub fn new() -> CocWindow {
+        CocWindow {
+            is_show: false,
+            skill_name: String::new(),
+            skill: 50,
+            bonus: 0,
+            penalty: 0,
+            san: 50,
+            success_loss: String::from("0"),
+            failure_loss: String::from("1d6"),
+            sanity_error: None,
+            investigator: None,
+            is_investigator_show: false,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        records: &mut RecordManager,
+        player: &SoundPlayer,
+        rd: &mut DiceRng,
+        ctx: &egui::CtxRef,
+    ) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Call of Cthulhu")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                egui::Grid::new("coc_skill_check")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Skill");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.skill_name)
+                                .hint_text("name (optional)")
+                                .desired_width(120.0),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut self.skill)
+                                .clamp_range::<i32>(1..=100)
+                                .speed(0.2),
+                        );
+                        ui.end_row();
+
+                        ui.strong("Bonus dice");
+                        ui.add(
+                            egui::DragValue::new(&mut self.bonus)
+                                .clamp_range::<i32>(0..=MAX_BONUS_DICE)
+                                .speed(0.05),
+                        );
+                        ui.end_row();
+
+                        ui.strong("Penalty dice");
+                        ui.add(
+                            egui::DragValue::new(&mut self.penalty)
+                                .clamp_range::<i32>(0..=MAX_BONUS_DICE)
+                                .speed(0.05),
+                        );
+                        ui.end_row();
+                    });
+
+                ui.horizontal(|ui| {
+                    let check = ui.add_sized(
+                        [100.0, 30.0],
+                        egui::Button::new(egui::RichText::new("Check").heading()),
+                    );
+                    if check.clicked_by(egui::PointerButton::Primary) {
+                        player.play();
+                        records.add_record(self.roll_check(rd));
+                    }
+                    if check.clicked_by(egui::PointerButton::Secondary) {
+                        records.add_record(self.roll_check(rd));
+                    }
+
+                    let d100 = ui
+                        .add_sized(
+                            [100.0, 30.0],
+                            egui::Button::new(egui::RichText::new("1D100").heading()),
+                        )
+                        .on_hover_text("Roll with the bonus/penalty dice but without a skill.");
+                    if d100.clicked_by(egui::PointerButton::Primary) {
+                        player.play();
+                        records.add_record(rd.replayable(|rd| {
+                            PercentileRoll::roll(self.bonus, self.penalty, rd).into_record()
+                        }));
+                    }
+                    if d100.clicked_by(egui::PointerButton::Secondary) {
+                        records.add_record(rd.replayable(|rd| {
+                            PercentileRoll::roll(self.bonus, self.penalty, rd).into_record()
+                        }));
+                    }
+                });
+
+                ui.separator();
+                egui::Grid::new("coc_sanity_check")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("SAN");
+                        ui.add(
+                            egui::DragValue::new(&mut self.san)
+                                .clamp_range::<i32>(0..=99)
+                                .speed(0.2),
+                        );
+                        ui.end_row();
+
+                        ui.strong("Loss");
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.success_loss)
+                                    .desired_width(50.0),
+                            )
+                            .on_hover_text("Lost on a success.");
+                            ui.label("/");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.failure_loss)
+                                    .desired_width(50.0),
+                            )
+                            .on_hover_text("Lost on a failure, the maximum on a fumble.");
+                        });
+                        ui.end_row();
+                    });
+
+                if let Some(e) = &self.sanity_error {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+
+                let sanity = ui.add_sized(
+                    [100.0, 30.0],
+                    egui::Button::new(egui::RichText::new("SAN check").heading()),
+                );
+                if sanity.clicked_by(egui::PointerButton::Primary) {
+                    if let Some(record) = self.roll_sanity(rd) {
+                        player.play();
+                        records.add_record(record);
+                    }
+                }
+                if sanity.clicked_by(egui::PointerButton::Secondary) {
+                    if let Some(record) = self.roll_sanity(rd) {
+                        records.add_record(record);
+                    }
+                }
+
+                ui.separator();
+                let investigator = ui
+                    .add_sized(
+                        [100.0, 30.0],
+                        egui::Button::new(egui::RichText::new("Investigator").heading()),
+                    )
+                    .on_hover_text("Roll all characteristics of a new investigator.");
+                if investigator.clicked_by(egui::PointerButton::Primary) {
+                    player.play();
+                    self.roll_investigator(records, rd);
+                }
+                if investigator.clicked_by(egui::PointerButton::Secondary) {
+                    self.roll_investigator(records, rd);
+                }
+            });
+        self.is_show = is_show;
+
+        if let Some(investigator) = &self.investigator {
+            egui::Window::new("Investigator")
+                .auto_sized()
+                .open(&mut self.is_investigator_show)
+                .show(ctx, |ui| investigator.show(ui));
+        }
+    }
+
+    fn roll_check(&self, rd: &mut DiceRng) -> Box<RollRecord> {
+        let mut record =
+            rd.replayable(|rd| SkillCheck::roll(self.skill, self.bonus, self.penalty, rd));
+        let name = self.skill_name.trim();
+        let bonus = record.percentile.as_ref().unwrap().describe_bonus();
+        record.description = if name.is_empty() {
+            format!("Check {}{}", self.skill, bonus)
+        } else {
+            record.label = Some(name.to_string());
+            format!("{} {}{}", name, self.skill, bonus)
+        };
+        record
+    }
+
+    /// The sheet opens in its own window.
+    fn roll_investigator(&mut self, records: &mut RecordManager, rd: &mut DiceRng) {
+        self.investigator = Some(Investigator::roll(records, rd));
+        self.is_investigator_show = true;
+    }
+
+    /// Keeps the SAN field at the sanity left after the check. The losses are parsed
+    /// before anything is rolled, so a typo in them does not use up a fair roll.
+    fn roll_sanity(&mut self, rd: &mut DiceRng) -> Option<Box<RollRecord>> {
+        if let Err(e) = SanityCheck::parse_losses(&self.success_loss, &self.failure_loss) {
+            self.sanity_error = Some(e);
+            return None;
+        }
+        let record = rd.replayable(|rd| {
+            SanityCheck::roll(self.san, &self.success_loss, &self.failure_loss, rd).unwrap()
+        });
+        self.sanity_error = None;
+        self.san = record.sanity.as_ref().unwrap().new_san();
+        Some(record)
+    }
+}
@@ -0,0 +1,233 @@
+//! Rolls of Dungeons & Dragons 5th edition.
+
+use super::super::sound::SoundPlayer;
+use super::rng::DiceRng;
+use super::{DicesState, RecordManager, RollRecord};
+use eframe::egui;
+
+/// Arrays thrown away by the house rule before giving up and keeping the last one.
+const MAX_ARRAYS: usize = 100;
+
+/// Point-buy cost of the scores 8..=15, 27 points in total for a standard character.
+const POINT_BUY_COST: [i32; 8] = [0, 1, 2, 3, 4, 5, 7, 9];
+const POINT_BUY_BUDGET: i32 = 27;
+
+/// How every one of the six scores is rolled.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AbilityMethod {
+    FourDropLowest,
+    ThreeStraight,
+    TwoPlusSix,
+}
+
+impl AbilityMethod {
+    const ALL: [AbilityMethod; 3] = [
+        AbilityMethod::FourDropLowest,
+        AbilityMethod::ThreeStraight,
+        AbilityMethod::TwoPlusSix,
+    ];
+
+    pub fn notation(&self) -> &'static str {
+        match self {
+            AbilityMethod::FourDropLowest => "4d6dl1",
+            AbilityMethod::ThreeStraight => "3d6",
+            AbilityMethod::TwoPlusSix => "2d6+6",
+        }
+    }
+
+    pub fn from_notation(notation: &str) -> Option<AbilityMethod> {
+        AbilityMethod::ALL
+            .iter()
+            .copied()
+            .find(|m| m.notation() == notation)
+    }
+
+    pub fn state(&self) -> DicesState {
+        DicesState::from_notation(self.notation()).unwrap()
+    }
+}
+
+/// e.g. `+2` or `-1`.
+pub fn format_modifier(modifier: i32) -> String {
+    format!("{:+}", modifier)
+}
+
+pub fn modifier(score: i32) -> i32 {
+    (score - 10).div_euclid(2)
+}
+
+/// Six ability scores rolled together.
+pub struct AbilityScores {
+    /// One roll for each score.
+    pub rolls: Vec<RollRecord>,
+    /// Whole arrays thrown away by the house rule before this one.
+    pub discarded: usize,
+    pub method: AbilityMethod,
+    pub min_modifier: Option<i32>,
+}
+
+impl AbilityScores {
+    /// Rolls arrays until the total modifier reaches `min_modifier`, if given.
+    pub fn roll(
+        method: AbilityMethod,
+        min_modifier: Option<i32>,
+        rd: &mut impl rand::Rng,
+    ) -> Box<RollRecord> {
+        let state = method.state();
+        let mut discarded = 0;
+        let scores = loop {
+            let scores = AbilityScores {
+                rolls: (0..6).map(|_| *state.roll(rd)).collect(),
+                discarded,
+                method,
+                min_modifier,
+            };
+            match min_modifier {
+                Some(min) if scores.total_modifier() < min && discarded < MAX_ARRAYS => {
+                    discarded += 1
+                }
+                _ => break scores,
+            }
+        };
+
+        AbilityScores::record(scores)
+    }
+
+    pub fn record(scores: AbilityScores) -> Box<RollRecord> {
+        let mut record = RollRecord::new(DicesState::new(Vec::new(), 0), Vec::new(), 0);
+        record.total = scores.scores().sum();
+        record.description = format!("Abilities {}", scores.method.state().gen_description());
+        record.label = Some("Abilities".to_string());
+        if scores.discarded != 0 {
+            record
+                .description
+                .push_str(&format!(" ({} rerolled)", scores.discarded));
+        }
+        record.abilities = Some(scores);
+        record
+    }
+
+    pub fn scores(&self) -> impl Iterator<Item = i32> + '_ {
+        self.rolls.iter().map(|r| r.total)
+    }
+
+    pub fn total_modifier(&self) -> i32 {
+        self.scores().map(modifier).sum()
+    }
+}
+
+/// What the scores would cost with point buy, `None` if one is out of 8..=15.
+pub fn point_buy(scores: &[i32]) -> Option<i32> {
+    scores
+        .iter()
+        .map(|&s| POINT_BUY_COST.get(usize::try_from(s - 8).ok()?).copied())
+        .sum()
+}
+
+pub fn show_point_buy(ui: &mut egui::Ui, scores: &[i32]) {
+    match point_buy(scores) {
+        Some(cost) => ui.label(format!("{} / {}", cost, POINT_BUY_BUDGET)),
+        None => ui
+            .label(egui::RichText::new("n/a").weak())
+            .on_hover_text("Point buy only has scores from 8 to 15."),
+    };
+}
+
+pub struct AbilityWindow {
+    pub is_show: bool,
+
+    method: AbilityMethod,
+    reroll_low: bool,
+    min_modifier: i32,
+    /// Scores of the last roll.
+    last: Vec<i32>,
+}
+
+impl AbilityWindow {
+    pub fn new() -> AbilityWindow {
+        AbilityWindow {
+            is_show: false,
+            method: AbilityMethod::FourDropLowest,
+            reroll_low: false,
+            min_modifier: 1,
+            last: Vec::new(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        records: &mut RecordManager,
+        player: &SoundPlayer,
+        rd: &mut DiceRng,
+        ctx: &egui::CtxRef,
+    ) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Ability scores")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for method in AbilityMethod::ALL {
+                        ui.selectable_value(&mut self.method, method, method.notation());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.reroll_low, "Reroll if total modifier below");
+                    ui.add_enabled(
+                        self.reroll_low,
+                        egui::DragValue::new(&mut self.min_modifier)
+                            .clamp_range::<i32>(-6..=6)
+                            .speed(0.05),
+                    );
+                });
+
+                let roll = ui.add_sized(
+                    [100.0, 30.0],
+                    egui::Button::new(egui::RichText::new("Roll").heading()),
+                );
+                if roll.clicked_by(egui::PointerButton::Primary) {
+                    player.play();
+                    records.add_record(self.roll(rd));
+                }
+                if roll.clicked_by(egui::PointerButton::Secondary) {
+                    records.add_record(self.roll(rd));
+                }
+
+                if !self.last.is_empty() {
+                    ui.separator();
+                    egui::Grid::new("ability_scores")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (i, &score) in self.last.iter().enumerate() {
+                                ui.label(format!("#{}", i + 1));
+                                ui.heading(score.to_string());
+                                ui.label(format_modifier(modifier(score)));
+                                ui.end_row();
+                            }
+                            ui.strong("Total");
+                            ui.heading(self.last.iter().sum::<i32>().to_string());
+                            ui.strong(format_modifier(
+                                self.last.iter().map(|&s| modifier(s)).sum(),
+                            ));
+                            ui.end_row();
+
+                            ui.strong("Point buy");
+                            show_point_buy(ui, &self.last);
+                            ui.end_row();
+                        });
+                }
+            });
+        self.is_show = is_show;
+    }
+
+    fn roll(&mut self, rd: &mut DiceRng) -> Box<RollRecord> {
+        let min_modifier = if self.reroll_low {
+            Some(self.min_modifier)
+        } else {
+            None
+        };
+        let record = rd.replayable(|rd| AbilityScores::roll(self.method, min_modifier, rd));
+        self.last = record.abilities.as_ref().unwrap().scores().collect();
+        record
+    }
+}
@@ -0,0 +1,439 @@
+//! Rolls as text for spreadsheets, wikis and forums.
+
+use super::{history, DieRecord, RecordLine, RollRecord};
+use chrono::TimeZone;
+use eframe::egui;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Markdown,
+    BbCode,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::Csv,
+        ExportFormat::Markdown,
+        ExportFormat::BbCode,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::BbCode => "BBCode",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Markdown => "md",
+            ExportFormat::BbCode => "txt",
+        }
+    }
+
+    /// How a dropped or rerolled dice is crossed out.
+    fn strike(&self, s: &str) -> String {
+        match self {
+            ExportFormat::Csv => format!("({})", s),
+            ExportFormat::Markdown => format!("~~{}~~", s),
+            ExportFormat::BbCode => format!("[s]{}[/s]", s),
+        }
+    }
+
+    fn cell(&self, s: &str) -> String {
+        match self {
+            ExportFormat::Csv if s.contains(&[',', '"', '\n', '\r'][..]) => {
+                format!("\"{}\"", s.replace('"', "\"\""))
+            }
+            ExportFormat::Csv | ExportFormat::BbCode => s.to_string(),
+            ExportFormat::Markdown => s.replace('|', "\\|").replace('\n', " "),
+        }
+    }
+
+    fn row(&self, cells: &[String]) -> String {
+        let cells: Vec<String> = cells.iter().map(|c| self.cell(c)).collect();
+        match self {
+            ExportFormat::Csv => cells.join(","),
+            ExportFormat::Markdown => format!("| {} |", cells.join(" | ")),
+            ExportFormat::BbCode => format!("[tr][td]{}[/td][/tr]", cells.join("[/td][td]")),
+        }
+    }
+
+    /// Time, description, dice, result and outcome of every record.
+    pub fn export<'a>(&self, records: impl Iterator<Item = &'a RollRecord>) -> String {
+        const HEADER: [&str; 5] = ["Time", "Description", "Dice", "Result", "Outcome"];
+        let mut lines = Vec::new();
+        match self {
+            ExportFormat::Csv => lines.push(HEADER.join(",")),
+            ExportFormat::Markdown => {
+                lines.push(format!("| {} |", HEADER.join(" | ")));
+                lines.push(format!("|{}", "---|".repeat(HEADER.len())));
+            }
+            ExportFormat::BbCode => {
+                lines.push("[table]".to_string());
+                lines.push(format!("[tr][th]{}[/th][/tr]", HEADER.join("[/th][th]")));
+            }
+        }
+        for record in records {
+            lines.push(self.row(&[
+                record.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                record.description.clone(),
+                self.dice(record),
+                record.result_text(),
+                outcome(record),
+            ]));
+        }
+        if *self == ExportFormat::BbCode {
+            lines.push("[/table]".to_string());
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Like the detail window shows it, e.g. `4D6dl1: 5 3 ~~1~~ 6`.
+    fn dice(&self, record: &RollRecord) -> String {
+        if !record.repeats.is_empty() {
+            let repeats: Vec<String> = record
+                .repeats
+                .iter()
+                .enumerate()
+                .map(|(i, r)| format!("#{} {}", i + 1, self.dice(r)))
+                .collect();
+            return repeats.join(" / ");
+        }
+        if let Some(abilities) = &record.abilities {
+            let rolls: Vec<String> = abilities.rolls.iter().map(|r| self.dice(r)).collect();
+            return rolls.join(" / ");
+        }
+
+        if let Some(percentile) = &record.percentile {
+            let tens: Vec<String> = percentile
+                .tens
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    if i == percentile.chosen {
+                        t.to_string()
+                    } else {
+                        self.strike(&t.to_string())
+                    }
+                })
+                .collect();
+            let mut text = format!("Tens: {}; Units: {}", tens.join(" "), percentile.units);
+            if let Some(loss_roll) = record.sanity.as_ref().and_then(|s| s.loss_roll.as_ref()) {
+                text.push_str(&format!("; Loss {}", self.dice(loss_roll)));
+            }
+            return text;
+        }
+
+        record
+            .state
+            .groups
+            .iter()
+            .zip(&record.records)
+            .filter(|(g, _)| g.count != 0)
+            .map(|(g, dice)| {
+                let dice: Vec<String> = dice.iter().map(|d| self.die(d)).collect();
+                let sign = if g.negative { "-" } else { "" };
+                format!("{}{}: {}", sign, g.notation(), dice.join(" "))
+            })
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    fn die(&self, die: &DieRecord) -> String {
+        let mut text: Vec<String> = die
+            .rerolled
+            .iter()
+            .map(|r| self.strike(&r.to_string()))
+            .collect();
+        let value = if die.rolls.len() > 1 {
+            let chain: Vec<String> = die.rolls.iter().map(|r| r.to_string()).collect();
+            format!("{}[{}]", die.value, chain.join("+"))
+        } else {
+            die.value.to_string()
+        };
+        text.push(if die.dropped {
+            self.strike(&value)
+        } else {
+            value
+        });
+        text.join(" ")
+    }
+}
+
+/// Success level, pass or fail against the target, e.g. `Hard` or `fail, margin -2`.
+fn outcome(record: &RollRecord) -> String {
+    let mut parts = Vec::new();
+    if let Some(check) = &record.check {
+        parts.push(check.level.name().to_string());
+    }
+    if let Some((pass, margin)) = record.outcome() {
+        let pass = if pass { "pass" } else { "fail" };
+        parts.push(format!("{}, margin {}", pass, margin));
+    }
+    if record.state.target.is_some() && !record.repeats.is_empty() {
+        let passes = record
+            .repeats
+            .iter()
+            .filter(|r| matches!(r.outcome(), Some((true, _))));
+        parts.push(format!("{}/{} pass", passes.count(), record.repeats.len()));
+    }
+    parts.join(", ")
+}
+
+/// `2021-12-24 20:30`, or only `20:30` for today. Seconds may be given. As the `end` of
+/// a range it is the last moment of that minute or second, so `To 22:00` takes in the
+/// rolls made during 22:00. A time skipped when the clocks go forward is an error.
+pub fn parse_time(s: &str, end: bool) -> Result<Option<chrono::DateTime<chrono::Local>>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let (date_time, seconds) = [("%Y-%m-%d %H:%M:%S", 1), ("%Y-%m-%d %H:%M", 60)]
+        .iter()
+        .find_map(|(f, seconds)| {
+            chrono::NaiveDateTime::parse_from_str(s, f)
+                .ok()
+                .map(|t| (t, *seconds))
+        })
+        .or_else(|| {
+            let today = chrono::Local::now().naive_local().date();
+            [("%H:%M:%S", 1), ("%H:%M", 60)]
+                .iter()
+                .find_map(|(f, seconds)| {
+                    chrono::NaiveTime::parse_from_str(s, f)
+                        .ok()
+                        .map(|t| (today.and_time(t), *seconds))
+                })
+        })
+        .ok_or(format!(
+            "'{}' is not a time like 2021-12-24 20:30 or 20:30",
+            s
+        ))?;
+    let date_time = if end {
+        date_time + chrono::Duration::seconds(seconds) - chrono::Duration::nanoseconds(1)
+    } else {
+        date_time
+    };
+    // A time given twice when the clocks go back covers both.
+    let time = match chrono::Local.from_local_datetime(&date_time) {
+        chrono::LocalResult::Single(time) => time,
+        chrono::LocalResult::Ambiguous(a, b) if end => a.max(b),
+        chrono::LocalResult::Ambiguous(a, b) => a.min(b),
+        chrono::LocalResult::None => {
+            return Err(format!("'{}' does not exist here, the clocks skip it", s))
+        }
+    };
+    Ok(Some(time))
+}
+
+/// Writes `text` with `rolls` in it to `path`, but never over a history file.
+pub fn save(path: &Path, text: &str, rolls: usize) -> Result<String, String> {
+    if history::is_history(path) {
+        return Err(format!(
+            "{} is a roll history, save to another name.",
+            path.display()
+        ));
+    }
+    match std::fs::write(path, text) {
+        Ok(_) => Ok(format!("Saved {} rolls to {}.", rolls, path.display())),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExportRange {
+    All,
+    Time,
+    Selected,
+}
+
+pub struct ExportWindow {
+    pub is_show: bool,
+
+    format: ExportFormat,
+    range: ExportRange,
+    from: String,
+    to: String,
+    /// Saved with the extension of the format, in the directory of the program.
+    file_name: String,
+    /// The file that is already there, waiting for the user to overwrite it.
+    overwrite: Option<PathBuf>,
+    /// What the last export did, or why it failed.
+    message: Option<Result<String, String>>,
+}
+
+impl Default for ExportWindow {
+    fn default() -> ExportWindow {
+        ExportWindow::new()
+    }
+}
+
+impl ExportWindow {
+    pub fn new() -> ExportWindow {
+        ExportWindow {
+            is_show: false,
+            format: ExportFormat::Csv,
+            range: ExportRange::All,
+            from: String::new(),
+            to: String::new(),
+            file_name: "rolls".to_string(),
+            overwrite: None,
+            message: None,
+        }
+    }
+
+    fn records<'a>(
+        &self,
+        table: &'a std::collections::VecDeque<RecordLine>,
+    ) -> Result<Vec<&'a RollRecord>, String> {
+        let (from, to) = match self.range {
+            ExportRange::Time => (parse_time(&self.from, false)?, parse_time(&self.to, true)?),
+            ExportRange::All | ExportRange::Selected => (None, None),
+        };
+        Ok(table
+            .iter()
+            .filter(|l| self.range != ExportRange::Selected || l.is_selected)
+            .map(|l| &*l.record)
+            .filter(|r| match (from, to) {
+                (Some(from), _) if r.time < from => false,
+                (_, Some(to)) if r.time > to => false,
+                _ => true,
+            })
+            .collect())
+    }
+
+    pub fn update(
+        &mut self,
+        table: &mut std::collections::VecDeque<RecordLine>,
+        ctx: &egui::CtxRef,
+    ) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Export")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for format in ExportFormat::ALL {
+                        ui.selectable_value(&mut self.format, format, format.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.range, ExportRange::All, "all");
+                    ui.selectable_value(&mut self.range, ExportRange::Time, "time range");
+                    ui.selectable_value(&mut self.range, ExportRange::Selected, "selected");
+                });
+
+                match self.range {
+                    ExportRange::All => {}
+                    ExportRange::Time => {
+                        egui::Grid::new("export_time").show(ui, |ui| {
+                            ui.strong("From");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.from)
+                                    .hint_text("2021-12-24 20:30")
+                                    .desired_width(140.0),
+                            );
+                            ui.end_row();
+                            ui.strong("To");
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.to)
+                                    .hint_text("22:00")
+                                    .desired_width(140.0),
+                            );
+                            ui.end_row();
+                        });
+                    }
+                    ExportRange::Selected => ExportWindow::show_selection(ui, table),
+                }
+
+                ui.separator();
+                let records = self.records(table);
+                match &records {
+                    Ok(records) => ui.label(format!("{} rolls", records.len())),
+                    Err(e) => ui.label(egui::RichText::new(e).color(egui::Color32::RED)),
+                };
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.file_name).desired_width(120.0));
+                    ui.label(format!(".{}", self.format.extension()));
+                });
+                ui.horizontal(|ui| {
+                    let records = match records {
+                        Ok(records) => records,
+                        Err(_) => return,
+                    };
+                    if ui.button("Copy").clicked() {
+                        ui.output().copied_text = self.format.export(records.iter().copied());
+                        self.message = Some(Ok(format!("Copied {} rolls.", records.len())));
+                    }
+                    let name = self.file_name.trim();
+                    let path =
+                        history::app_dir().join(format!("{}.{}", name, self.format.extension()));
+                    if self.overwrite.as_ref() != Some(&path) {
+                        self.overwrite = None;
+                    }
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("Save"))
+                        .clicked()
+                    {
+                        if path.exists() {
+                            self.overwrite = Some(path.clone());
+                        } else {
+                            let text = self.format.export(records.iter().copied());
+                            self.message = Some(save(&path, &text, records.len()));
+                        }
+                    }
+                    if self.overwrite.is_none() {
+                        return;
+                    }
+                    ui.label(format!("{} exists.", path.display()));
+                    if ui.button("Overwrite").clicked() {
+                        let text = self.format.export(records.iter().copied());
+                        self.message = Some(save(&path, &text, records.len()));
+                        self.overwrite = None;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.overwrite = None;
+                    }
+                });
+                match &self.message {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(e)) => {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+                    None => {}
+                }
+            });
+        self.is_show = is_show;
+    }
+
+    fn show_selection(ui: &mut egui::Ui, table: &mut std::collections::VecDeque<RecordLine>) {
+        ui.horizontal(|ui| {
+            if ui.small_button("all").clicked() {
+                table.iter_mut().for_each(|l| l.is_selected = true);
+            }
+            if ui.small_button("none").clicked() {
+                table.iter_mut().for_each(|l| l.is_selected = false);
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for line in table.iter_mut() {
+                    let text = format!(
+                        "{}  {}  => {}",
+                        line.record.time.format("%H:%M:%S"),
+                        line.record.description,
+                        line.record.result_text()
+                    );
+                    ui.checkbox(&mut line.is_selected, text);
+                }
+            });
+    }
+}
@@ -0,0 +1,239 @@
+//! Arithmetic on the subtotals of dice groups, e.g. `(2d6+6)*5` or `floor(1d6/2)`.
+
+/// Values are exact until the end: only [`Expr::Round`] and the final total round them.
+#[derive(Clone)]
+pub enum Expr {
+    /// Subtotal of the dice group at this index of `DicesState::groups`.
+    Dice(usize),
+    Constant(i32),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Round(Rounding, Box<Expr>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::Mul | BinaryOp::Div => 2,
+        }
+    }
+
+    /// Division by zero gives 0 instead of failing the whole roll.
+    pub fn apply(&self, lhs: f64, rhs: f64) -> f64 {
+        match self {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div if rhs == 0.0 => 0.0,
+            BinaryOp::Div => lhs / rhs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    Round,
+}
+
+impl Rounding {
+    pub fn from_name(name: &str) -> Option<Rounding> {
+        match name.to_ascii_lowercase().as_str() {
+            "floor" => Some(Rounding::Floor),
+            "ceil" => Some(Rounding::Ceil),
+            "round" => Some(Rounding::Round),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Rounding::Floor => "floor",
+            Rounding::Ceil => "ceil",
+            Rounding::Round => "round",
+        }
+    }
+
+    /// `Round` rounds halves away from zero.
+    pub fn apply(&self, v: f64) -> f64 {
+        match self {
+            Rounding::Floor => v.floor(),
+            Rounding::Ceil => v.ceil(),
+            Rounding::Round => v.round(),
+        }
+    }
+}
+
+/// e.g. `3`, `3.5` or `0.33`.
+pub fn format_value(v: f64) -> String {
+    if v.fract() == 0.0 {
+        format!("{}", v as i64)
+    } else {
+        let s = format!("{:.2}", v);
+        s.trim_end_matches('0').to_string()
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, ..) => op.precedence(),
+            Expr::Neg(_) => 3,
+            Expr::Dice(_) | Expr::Constant(_) | Expr::Round(..) => 4,
+        }
+    }
+
+    /// Writes the expression with `leaf` for every dice group and only the parentheses needed.
+    pub fn format(&self, leaf: &impl Fn(usize) -> String) -> String {
+        let wrap = |e: &Expr, parens: bool| {
+            if parens {
+                format!("({})", e.format(leaf))
+            } else {
+                e.format(leaf)
+            }
+        };
+        match self {
+            Expr::Dice(i) => leaf(*i),
+            Expr::Constant(c) => c.to_string(),
+            Expr::Neg(e) => format!("-{}", wrap(e, e.precedence() <= 3)),
+            Expr::Binary(op, lhs, rhs) => {
+                // `a - (b - c)` and `a / (b / c)` keep their parentheses.
+                let associative = matches!(op, BinaryOp::Add | BinaryOp::Mul);
+                let rhs_parens = if associative {
+                    rhs.precedence() < op.precedence()
+                } else {
+                    rhs.precedence() <= op.precedence()
+                };
+                format!(
+                    "{} {} {}",
+                    wrap(lhs, lhs.precedence() < op.precedence()),
+                    op.symbol(),
+                    wrap(rhs, rhs_parens)
+                )
+            }
+            Expr::Round(r, e) => format!("{}({})", r.name(), e.format(leaf)),
+        }
+    }
+
+    /// Evaluates with the `subtotals` of the dice groups, pushing every operation
+    /// done onto `steps`, e.g. `7 + 6 = 13`.
+    pub fn eval(&self, subtotals: &[i32], steps: &mut Vec<String>) -> f64 {
+        match self {
+            Expr::Dice(i) => subtotals[*i] as f64,
+            Expr::Constant(c) => *c as f64,
+            Expr::Neg(e) => -e.eval(subtotals, steps),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(subtotals, steps);
+                let rhs = rhs.eval(subtotals, steps);
+                let v = op.apply(lhs, rhs);
+                steps.push(format!(
+                    "{} {} {} = {}",
+                    format_value(lhs),
+                    op.symbol(),
+                    format_value(rhs),
+                    format_value(v)
+                ));
+                v
+            }
+            Expr::Round(r, e) => {
+                let inner = e.eval(subtotals, steps);
+                let v = r.apply(inner);
+                steps.push(format!(
+                    "{}({}) = {}",
+                    r.name(),
+                    format_value(inner),
+                    format_value(v)
+                ));
+                v
+            }
+        }
+    }
+
+    /// The total is rounded down.
+    pub fn total(&self, subtotals: &[i32]) -> i32 {
+        self.eval(subtotals, &mut Vec::new()).floor() as i32
+    }
+
+    /// Lowest and highest values, given those of every dice group.
+    pub fn range(&self, leaf: &impl Fn(usize) -> (i32, i32)) -> (f64, f64) {
+        match self {
+            Expr::Dice(i) => {
+                let (min, max) = leaf(*i);
+                (min as f64, max as f64)
+            }
+            Expr::Constant(c) => (*c as f64, *c as f64),
+            Expr::Neg(e) => {
+                let (min, max) = e.range(leaf);
+                (-max, -min)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (a, b) = lhs.range(leaf);
+                let (c, d) = rhs.range(leaf);
+                match op {
+                    BinaryOp::Add => (a + c, b + d),
+                    BinaryOp::Sub => (a - d, b - c),
+                    BinaryOp::Mul | BinaryOp::Div => {
+                        let mut rhs = vec![c, d];
+                        if *op == BinaryOp::Div && c <= 0.0 && d >= 0.0 {
+                            // Subtotals are integers, so the closest to zero are -1 and 1.
+                            rhs.extend([-1.0, 1.0].iter().filter(|&&y| c <= y && y <= d));
+                        }
+                        let values: Vec<f64> = [a, b]
+                            .iter()
+                            .flat_map(|&x| rhs.iter().map(move |&y| op.apply(x, y)))
+                            .collect();
+                        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+                        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                        (min, max)
+                    }
+                }
+            }
+            Expr::Round(r, e) => {
+                let (min, max) = e.range(leaf);
+                (r.apply(min), r.apply(max))
+            }
+        }
+    }
+
+    /// Adds up a plain sum of dice and constants, setting the sign of every dice group
+    /// in `negative`. `false` if there is anything else in the expression.
+    pub fn flatten(&self, minus: bool, negative: &mut [bool], constant: &mut i32) -> bool {
+        match self {
+            Expr::Dice(i) => {
+                negative[*i] = minus;
+                true
+            }
+            Expr::Constant(c) => {
+                *constant += if minus { -c } else { *c };
+                true
+            }
+            Expr::Neg(e) => e.flatten(!minus, negative, constant),
+            Expr::Binary(BinaryOp::Add, lhs, rhs) => {
+                lhs.flatten(minus, negative, constant) && rhs.flatten(minus, negative, constant)
+            }
+            Expr::Binary(BinaryOp::Sub, lhs, rhs) => {
+                lhs.flatten(minus, negative, constant) && rhs.flatten(!minus, negative, constant)
+            }
+            _ => false,
+        }
+    }
+}
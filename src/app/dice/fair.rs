@@ -0,0 +1,296 @@
+//! Provably fair rolls by commit and reveal.
+//!
+//! The app picks a secret seed and publishes its SHA-256 before the session. Every roll
+//! is then made by ChaCha20 seeded with the SHA-256 of `<secret seed hex>:<player seed>:<nonce>`,
+//! so neither side alone decides the numbers. After the secret is revealed, anyone can
+//! check it against the commitment and roll every record again. The rolls are numbered
+//! by their nonce, so a roll thrown away shows up as a gap.
+//!
+//! The secret is only kept in memory until it is revealed: a session the app does not
+//! reveal before it closes can never be checked.
+
+use super::history;
+use super::sha256;
+use super::{RecordManager, RollRecord};
+use eframe::egui;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// What a fair roll needs to be checked once the secret seed is revealed.
+#[derive(Clone)]
+pub struct FairRoll {
+    /// SHA-256 of the secret seed, in hex.
+    pub commitment: String,
+    pub player_seed: String,
+    pub nonce: u64,
+}
+
+impl FairRoll {
+    /// The generator the roll was made with, if `secret` is the seed of the commitment.
+    pub fn verify(&self, secret: &str) -> Result<ChaCha20Rng, String> {
+        let secret = secret.trim().to_ascii_lowercase();
+        let bytes = sha256::from_hex(&secret).ok_or("The seed is not in hex.")?;
+        if sha256::to_hex(&sha256::digest(&bytes)) != self.commitment {
+            return Err("The seed does not match the commitment.".to_string());
+        }
+        Ok(roll_rng(&secret, &self.player_seed, self.nonce))
+    }
+}
+
+fn roll_rng(secret: &str, player_seed: &str, nonce: u64) -> ChaCha20Rng {
+    let message = format!("{}:{}:{}", secret, player_seed, nonce);
+    ChaCha20Rng::from_seed(sha256::digest(message.as_bytes()))
+}
+
+/// The secret seed of a session and the rolls made with it so far.
+pub struct FairSession {
+    secret: [u8; 32],
+    commitment: String,
+    player_seed: String,
+    nonce: u64,
+}
+
+impl FairSession {
+    pub fn new() -> FairSession {
+        let mut secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        FairSession {
+            secret,
+            commitment: sha256::to_hex(&sha256::digest(&secret)),
+            player_seed: String::new(),
+            nonce: 0,
+        }
+    }
+
+    pub fn commitment(&self) -> &str {
+        &self.commitment
+    }
+
+    /// The player seed, only while it can still be changed: the commitment covers
+    /// every roll made with the same one, so it is fixed from the first roll on.
+    pub fn player_seed_mut(&mut self) -> Option<&mut String> {
+        if self.nonce == 0 {
+            Some(&mut self.player_seed)
+        } else {
+            None
+        }
+    }
+
+    pub fn player_seed(&self) -> &str {
+        &self.player_seed
+    }
+
+    /// Rolls made so far.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// The generator of the next roll.
+    pub fn next(&mut self) -> (FairRoll, ChaCha20Rng) {
+        let roll = FairRoll {
+            commitment: self.commitment.clone(),
+            player_seed: self.player_seed.clone(),
+            nonce: self.nonce,
+        };
+        self.nonce += 1;
+        let rng = roll_rng(&sha256::to_hex(&self.secret), &roll.player_seed, roll.nonce);
+        (roll, rng)
+    }
+
+    /// Ends the session, giving away its secret seed.
+    pub fn reveal(self) -> Revealed {
+        Revealed {
+            secret: sha256::to_hex(&self.secret),
+            rolls: self.nonce,
+        }
+    }
+}
+
+/// The end of a fair session.
+pub struct Revealed {
+    /// In hex.
+    pub secret: String,
+    /// Made with the secret, numbered from 0.
+    pub rolls: u64,
+}
+
+/// A record checked against a revealed seed.
+struct Verdict {
+    nonce: u64,
+    description: String,
+    result: String,
+    rolled_again: Option<String>,
+}
+
+impl Verdict {
+    fn is_fair(&self) -> bool {
+        self.rolled_again.as_ref() == Some(&self.result)
+    }
+}
+
+pub struct VerifyWindow {
+    pub is_show: bool,
+
+    secret: String,
+    /// Of the seed revealed last, to tell the rolls missing from the records.
+    revealed: Option<Revealed>,
+    error: Option<String>,
+    verdicts: Vec<Verdict>,
+    /// Nonces no record has, and nonces more than one record has.
+    missing: Vec<u64>,
+    repeated: Vec<u64>,
+}
+
+impl VerifyWindow {
+    pub fn new() -> VerifyWindow {
+        VerifyWindow {
+            is_show: false,
+            secret: String::new(),
+            revealed: None,
+            error: None,
+            verdicts: Vec::new(),
+            missing: Vec::new(),
+            repeated: Vec::new(),
+        }
+    }
+
+    /// Opens the window to check the records of a seed just revealed.
+    pub fn open(&mut self, revealed: Revealed, records: &RecordManager) {
+        self.secret = revealed.secret.clone();
+        self.revealed = Some(revealed);
+        self.is_show = true;
+        self.verify(records);
+    }
+
+    /// Rolls every fair record of the seed in the history again, and looks for the
+    /// rolls that are not there. Records of other sessions are left out.
+    fn verify(&mut self, records: &RecordManager) {
+        self.verdicts.clear();
+        let mut mismatch = None;
+        // The history has every roll, the table only the last ones and not the cleared.
+        let log = history::read(records.history.path(), usize::MAX);
+        let logged: Vec<&RollRecord> = match &log {
+            Ok(log) => log.records.iter().collect(),
+            Err(e) => {
+                mismatch = Some(format!("Only the rolls shown are checked: {}", e));
+                records.table.iter().map(|l| &*l.record).collect()
+            }
+        };
+        for record in logged {
+            let fair = match &record.fair {
+                Some(fair) => fair,
+                None => continue,
+            };
+            match fair.verify(&self.secret) {
+                Ok(mut rd) => self.verdicts.push(Verdict {
+                    nonce: fair.nonce,
+                    description: record.description.clone(),
+                    result: record.result_text(),
+                    rolled_again: record.rolled_again(&mut rd).map(|r| r.result_text()),
+                }),
+                Err(e) => mismatch = Some(e),
+            }
+        }
+        self.error = if self.verdicts.is_empty() {
+            Some(mismatch.unwrap_or_else(|| "There is no fair roll in the records.".to_string()))
+        } else {
+            None
+        };
+
+        let rolls = match &self.revealed {
+            Some(revealed) if revealed.secret == self.secret.trim().to_ascii_lowercase() => {
+                revealed.rolls
+            }
+            _ => self.verdicts.iter().map(|v| v.nonce + 1).max().unwrap_or(0),
+        };
+        let mut count = vec![0; rolls as usize];
+        for v in self.verdicts.iter() {
+            if let Some(c) = count.get_mut(v.nonce as usize) {
+                *c += 1;
+            }
+        }
+        let nonces = |is: fn(usize) -> bool| -> Vec<u64> {
+            (0..rolls).filter(|&n| is(count[n as usize])).collect()
+        };
+        self.missing = nonces(|c| c == 0);
+        self.repeated = nonces(|c| c > 1);
+    }
+
+    pub fn update(&mut self, records: &RecordManager, ctx: &egui::CtxRef) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Verify fair rolls")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("Revealed seed");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.secret)
+                            .hint_text("hex")
+                            .desired_width(300.0),
+                    );
+                    if ui.button("Verify").clicked() {
+                        self.verify(records);
+                    }
+                });
+                if let Some(e) = &self.error {
+                    ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                }
+                if self.verdicts.is_empty() {
+                    return;
+                }
+
+                let fair = self.verdicts.iter().filter(|v| v.is_fair()).count();
+                ui.label(format!("{} of {} rolls match.", fair, self.verdicts.len()));
+                let list = |nonces: &[u64]| {
+                    let nonces: Vec<String> = nonces.iter().map(|n| format!("#{}", n)).collect();
+                    nonces.join(", ")
+                };
+                if !self.missing.is_empty() {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Not in the history: {}.",
+                            list(&self.missing)
+                        ))
+                        .color(egui::Color32::RED),
+                    )
+                    .on_hover_text("A roll made but not kept, or saved to another history.");
+                }
+                if !self.repeated.is_empty() {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "In the history more than once: {}.",
+                            list(&self.repeated)
+                        ))
+                        .color(egui::Color32::RED),
+                    );
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("fair_verdicts")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for v in self.verdicts.iter() {
+                                    ui.label(format!("#{}", v.nonce));
+                                    ui.label(&v.description);
+                                    ui.strong(&v.result);
+                                    if v.is_fair() {
+                                        ui.label(
+                                            egui::RichText::new("✔").color(egui::Color32::GREEN),
+                                        );
+                                    } else {
+                                        let again = v.rolled_again.as_deref().unwrap_or("nothing");
+                                        ui.label(
+                                            egui::RichText::new(format!("✖ {}", again))
+                                                .color(egui::Color32::RED),
+                                        );
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        self.is_show = is_show;
+    }
+}
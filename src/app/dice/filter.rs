@@ -0,0 +1,275 @@
+//! The filter bar over the record table.
+
+use super::coc::SuccessLevel;
+use super::export::parse_time;
+use super::{RecordLine, RollRecord};
+use eframe::egui;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutcomeFilter {
+    Any,
+    Pass,
+    Fail,
+    /// A CoC critical or a natural 20.
+    Critical,
+    /// A CoC fumble or a natural 1.
+    Fumble,
+}
+
+impl OutcomeFilter {
+    const ALL: [OutcomeFilter; 5] = [
+        OutcomeFilter::Any,
+        OutcomeFilter::Pass,
+        OutcomeFilter::Fail,
+        OutcomeFilter::Critical,
+        OutcomeFilter::Fumble,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            OutcomeFilter::Any => "any outcome",
+            OutcomeFilter::Pass => "pass",
+            OutcomeFilter::Fail => "fail",
+            OutcomeFilter::Critical => "critical",
+            OutcomeFilter::Fumble => "fumble",
+        }
+    }
+
+    fn matches(&self, record: &RollRecord) -> bool {
+        let level = record.check.as_ref().map(|c| c.level);
+        let pass = match (level, record.outcome()) {
+            (Some(level), _) => Some(level.is_success()),
+            (None, Some((pass, _))) => Some(pass),
+            (None, None) => None,
+        };
+        match self {
+            OutcomeFilter::Any => true,
+            OutcomeFilter::Pass => pass == Some(true),
+            OutcomeFilter::Fail => pass == Some(false),
+            OutcomeFilter::Critical => {
+                level == Some(SuccessLevel::Critical) || natural_d20(record) == Some(20)
+            }
+            OutcomeFilter::Fumble => {
+                level == Some(SuccessLevel::Fumble) || natural_d20(record) == Some(1)
+            }
+        }
+    }
+}
+
+/// The face of the only D20 kept in a roll, like in `1d20+5`, `2d20kh1` or `1d20+1d4`.
+fn natural_d20(record: &RollRecord) -> Option<i32> {
+    if record.state.is_pool() {
+        return None;
+    }
+    let mut kept = record
+        .state
+        .groups
+        .iter()
+        .zip(&record.records)
+        .filter(|(g, _)| g.sides == 20 && g.count != 0)
+        .flat_map(|(_, dice)| dice.iter())
+        .filter(|d| !d.dropped);
+    match (kept.next(), kept.next()) {
+        (Some(d), None) => Some(d.value),
+        _ => None,
+    }
+}
+
+/// What the rows are checked against, parsed from the bar.
+#[derive(Default)]
+pub struct Criteria {
+    text: String,
+    sides: Option<i32>,
+    outcome: Option<OutcomeFilter>,
+    label: Option<String>,
+    min: Option<i32>,
+    max: Option<i32>,
+    from: Option<chrono::DateTime<chrono::Local>>,
+    to: Option<chrono::DateTime<chrono::Local>>,
+}
+
+impl Criteria {
+    pub fn matches(&self, record: &RollRecord) -> bool {
+        if !self.text.is_empty() && !record.description.to_lowercase().contains(&self.text) {
+            return false;
+        }
+        if let Some(sides) = self.sides {
+            let has_sides = record
+                .state
+                .groups
+                .iter()
+                .any(|g| g.sides == sides && g.count != 0);
+            if !has_sides {
+                return false;
+            }
+        }
+        if let Some(outcome) = self.outcome {
+            if !outcome.matches(record) {
+                return false;
+            }
+        }
+        if self.label.is_some() && record.label != self.label {
+            return false;
+        }
+        match (self.min, self.max, self.from, self.to) {
+            (Some(min), _, _, _) if record.total < min => false,
+            (_, Some(max), _, _) if record.total > max => false,
+            (_, _, Some(from), _) if record.time < from => false,
+            (_, _, _, Some(to)) if record.time > to => false,
+            _ => true,
+        }
+    }
+}
+
+pub struct RecordFilter {
+    text: String,
+    sides: Option<i32>,
+    outcome: OutcomeFilter,
+    label: Option<String>,
+    min: String,
+    max: String,
+    from: String,
+    to: String,
+}
+
+impl Default for RecordFilter {
+    fn default() -> RecordFilter {
+        RecordFilter::new()
+    }
+}
+
+impl RecordFilter {
+    pub fn new() -> RecordFilter {
+        RecordFilter {
+            text: String::new(),
+            sides: None,
+            outcome: OutcomeFilter::Any,
+            label: None,
+            min: String::new(),
+            max: String::new(),
+            from: String::new(),
+            to: String::new(),
+        }
+    }
+
+    fn criteria(&self) -> Result<Criteria, String> {
+        let number = |s: &str| match s.trim() {
+            "" => Ok(None),
+            s => s
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("'{}' is not a number", s)),
+        };
+        Ok(Criteria {
+            text: self.text.trim().to_lowercase(),
+            sides: self.sides,
+            outcome: match self.outcome {
+                OutcomeFilter::Any => None,
+                outcome => Some(outcome),
+            },
+            label: self.label.clone(),
+            min: number(&self.min)?,
+            max: number(&self.max)?,
+            from: parse_time(&self.from, false)?,
+            to: parse_time(&self.to, true)?,
+        })
+    }
+
+    /// Shows the bar with the count of matching rows. Nothing is filtered out while a
+    /// field is invalid.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        table: &std::collections::VecDeque<RecordLine>,
+    ) -> Criteria {
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.text)
+                    .hint_text("description")
+                    .desired_width(110.0),
+            );
+
+            let sides_text = |sides: Option<i32>| match sides {
+                Some(sides) => format!("D{}", sides),
+                None => "any dice".to_string(),
+            };
+            egui::ComboBox::from_id_source("filter_sides")
+                .selected_text(sides_text(self.sides))
+                .width(70.0)
+                .show_ui(ui, |ui| {
+                    let mut sides: Vec<i32> = table
+                        .iter()
+                        .flat_map(|l| l.record.state.groups.iter())
+                        .filter(|g| g.count != 0)
+                        .map(|g| g.sides)
+                        .collect();
+                    sides.sort_unstable();
+                    sides.dedup();
+                    ui.selectable_value(&mut self.sides, None, sides_text(None));
+                    for s in sides {
+                        ui.selectable_value(&mut self.sides, Some(s), sides_text(Some(s)));
+                    }
+                });
+
+            egui::ComboBox::from_id_source("filter_outcome")
+                .selected_text(self.outcome.name())
+                .width(90.0)
+                .show_ui(ui, |ui| {
+                    for outcome in OutcomeFilter::ALL {
+                        ui.selectable_value(&mut self.outcome, outcome, outcome.name());
+                    }
+                });
+
+            egui::ComboBox::from_id_source("filter_label")
+                .selected_text(self.label.as_deref().unwrap_or("any label"))
+                .width(90.0)
+                .show_ui(ui, |ui| {
+                    let mut labels: Vec<&str> = table
+                        .iter()
+                        .filter_map(|l| l.record.label.as_deref())
+                        .collect();
+                    labels.sort_unstable();
+                    labels.dedup();
+                    ui.selectable_value(&mut self.label, None, "any label");
+                    for l in labels {
+                        ui.selectable_value(&mut self.label, Some(l.to_string()), l);
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("result");
+            ui.add(egui::TextEdit::singleline(&mut self.min).desired_width(36.0));
+            ui.label("~");
+            ui.add(egui::TextEdit::singleline(&mut self.max).desired_width(36.0));
+            ui.label("time");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.from)
+                    .hint_text("20:30")
+                    .desired_width(110.0),
+            );
+            ui.label("~");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.to)
+                    .hint_text("2021-12-24 22:00")
+                    .desired_width(110.0),
+            );
+            if ui.small_button("reset").clicked() {
+                *self = RecordFilter::new();
+            }
+        });
+
+        match self.criteria() {
+            Ok(criteria) => {
+                let count = table.iter().filter(|l| criteria.matches(&l.record)).count();
+                ui.label(format!("{} of {} rolls", count, table.len()));
+                criteria
+            }
+            Err(e) => {
+                ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                Criteria::default()
+            }
+        }
+    }
+}
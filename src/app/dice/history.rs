@@ -0,0 +1,914 @@
+//! The roll history on disk, so a session survives restarts and crashes.
+//!
+//! Every roll is appended to the history file as one tab-separated line as soon as it is
+//! made:
+//!
+//! ```text
+//! <date and time> <description> <label> <origin> <numbers> <total> <kind> <arguments>... <dice>...
+//! ```
+//!
+//! A line keeps the result and every dice as they were shown. Loading gives them back as
+//! they are.
+//!
+//! The label is the one the filter goes by, empty for a roll without one.
+//!
+//! The numbers are the ones the random source gave out for the roll, up to
+//! [`TAPE_LIMIT`]. They are only a check: rolling the [`Recipe`] again on them must give
+//! the same dice, or the roll is reported as changed. That happens when the dice engine of
+//! another version reads numbers differently. A seeded roll is checked from its seed
+//! instead, and a fair roll once its secret is revealed, so theirs are not kept.
+//!
+//! The first line names the format, so a file written by another version is left alone
+//! instead of being misread. The file of a named session has its name on the second
+//! line, `session<tab><name>`.
+
+use super::fair::FairRoll;
+use super::rng::{Replay, Tape};
+use super::{coc, dnd, DicesState, DieRecord, RollRecord};
+use rand::Rng;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Of the rolls made outside of any session, in [`app_dir`].
+const FILE_NAME: &str = "history.txt";
+const HEADER: &str = "dice_redo history 2";
+/// The start of the header of every version.
+const FORMAT: &str = "dice_redo history";
+const SESSION: &str = "session";
+/// A file is read from its end in pieces of this size, until it has the records shown.
+const READ_CHUNK: u64 = 64 << 10;
+/// The header lines are looked for in so much of the start of a long file.
+const HEAD_LIMIT: u64 = 4096;
+/// Past this size, the history outside of any session is moved aside to the first free
+/// `history.old.<n>.txt` and a new one is started with the records shown. Sessions are
+/// kept whole.
+const ROTATE_LIMIT: u64 = 16 << 20;
+/// Numbers kept for a roll at most. A roll that took more, like a large pool, is kept
+/// without them, so no line grows past a few dice.
+const TAPE_LIMIT: usize = 64;
+
+/// The directory of the program, where its files are kept whatever the working
+/// directory is.
+pub fn app_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+/// The file of the rolls made outside of any session.
+pub fn default_path() -> PathBuf {
+    app_dir().join(FILE_NAME)
+}
+
+/// What a record is a roll of, enough to roll it again.
+pub enum Recipe {
+    Dice(DicesState),
+    D100 {
+        bonus: i32,
+    },
+    Check {
+        skill: i32,
+        bonus: i32,
+    },
+    Sanity {
+        san: i32,
+        success_loss: String,
+        failure_loss: String,
+    },
+    Abilities {
+        method: dnd::AbilityMethod,
+        min_modifier: Option<i32>,
+    },
+}
+
+impl Recipe {
+    pub fn of(record: &RollRecord) -> Option<Recipe> {
+        if let Some(sanity) = &record.sanity {
+            return Some(Recipe::Sanity {
+                san: sanity.san,
+                success_loss: sanity.success_loss.clone(),
+                failure_loss: sanity.failure_loss.clone(),
+            });
+        }
+        if let Some(abilities) = &record.abilities {
+            return Some(Recipe::Abilities {
+                method: abilities.method,
+                min_modifier: abilities.min_modifier,
+            });
+        }
+        match (&record.percentile, &record.check) {
+            (Some(percentile), Some(check)) => Some(Recipe::Check {
+                skill: check.skill,
+                bonus: percentile.bonus,
+            }),
+            (Some(percentile), None) => Some(Recipe::D100 {
+                bonus: percentile.bonus,
+            }),
+            (None, _) if record.state.valid() => Some(Recipe::Dice(record.state.clone())),
+            (None, _) => None,
+        }
+    }
+
+    /// The description is that of a fresh roll, not the one shown for the record.
+    pub fn roll(&self, rd: &mut impl Rng) -> Option<Box<RollRecord>> {
+        match self {
+            Recipe::Dice(state) => Some(state.roll(rd)),
+            Recipe::D100 { bonus } => Some(coc::PercentileRoll::roll(*bonus, 0, rd).into_record()),
+            Recipe::Check { skill, bonus } => Some(coc::SkillCheck::roll(*skill, *bonus, 0, rd)),
+            Recipe::Sanity {
+                san,
+                success_loss,
+                failure_loss,
+            } => coc::SanityCheck::roll(*san, success_loss, failure_loss, rd).ok(),
+            Recipe::Abilities {
+                method,
+                min_modifier,
+            } => Some(dnd::AbilityScores::roll(*method, *min_modifier, rd)),
+        }
+    }
+}
+
+/// Tabs and line breaks would split the line.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// `-`, `seed <seed> <position>` or `fair <nonce> <commitment> <player seed>`.
+fn format_origin(record: &RollRecord) -> String {
+    if let Some(replay) = record.replay {
+        format!("seed {} {}", replay.seed, replay.word_pos)
+    } else if let Some(fair) = &record.fair {
+        format!(
+            "fair {} {} {}",
+            fair.nonce, fair.commitment, fair.player_seed
+        )
+    } else {
+        "-".to_string()
+    }
+}
+
+fn parse_origin(s: &str, record: &mut RollRecord) -> Option<()> {
+    let mut parts = s.splitn(4, ' ');
+    match parts.next()? {
+        "-" => {}
+        "seed" => {
+            record.replay = Some(Replay {
+                seed: parts.next()?.parse().ok()?,
+                word_pos: parts.next()?.parse().ok()?,
+            })
+        }
+        "fair" => {
+            let nonce = parts.next()?.parse().ok()?;
+            // A SHA-256 in hex.
+            let commitment = parts.next()?;
+            if commitment.len() != 64 || !commitment.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            record.fair = Some(FairRoll {
+                nonce,
+                commitment: commitment.to_string(),
+                player_seed: parts.next().unwrap_or_default().to_string(),
+            })
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// `5`, `6+6+2` for an exploding chain, `1~4` after a reroll of 1, `(1)` when dropped.
+fn format_die(die: &DieRecord) -> String {
+    let mut faces: Vec<String> = die.rerolled.iter().map(|r| r.to_string()).collect();
+    let rolls: Vec<String> = die.rolls.iter().map(|r| r.to_string()).collect();
+    faces.push(rolls.join("+"));
+    let text = faces.join("~");
+    if die.dropped {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn parse_die(s: &str) -> Option<DieRecord> {
+    let (text, dropped) = match s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(text) => (text, true),
+        None => (s, false),
+    };
+    let mut faces: Vec<&str> = text.split('~').collect();
+    let rolls = faces
+        .pop()?
+        .split('+')
+        .map(|r| r.parse().ok())
+        .collect::<Option<Vec<i32>>>()?;
+    let rerolled = faces
+        .iter()
+        .map(|r| r.parse().ok())
+        .collect::<Option<Vec<i32>>>()?;
+    Some(DieRecord {
+        value: rolls.iter().sum(),
+        rolls,
+        rerolled,
+        dropped,
+    })
+}
+
+/// The dice of a roll of a [`DicesState`]: `3 4; 2` for two groups, the rolls of `N#`
+/// separated by ` | `.
+fn format_dice(record: &RollRecord) -> String {
+    if !record.repeats.is_empty() {
+        let repeats: Vec<String> = record.repeats.iter().map(format_dice).collect();
+        return repeats.join(" | ");
+    }
+    record
+        .state
+        .groups
+        .iter()
+        .zip(&record.records)
+        .filter(|(g, _)| g.count != 0)
+        .map(|(_, dice)| {
+            let dice: Vec<String> = dice.iter().map(format_die).collect();
+            dice.join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+fn parse_dice(state: &DicesState, s: &str) -> Option<Box<RollRecord>> {
+    if state.repeat > 1 {
+        let single = DicesState {
+            repeat: 1,
+            ..state.clone()
+        };
+        let repeats = s
+            .split(" | ")
+            .map(|r| parse_dice(&single, r).map(|r| *r))
+            .collect::<Option<Vec<RollRecord>>>()?;
+        if repeats.len() != state.repeat as usize {
+            return None;
+        }
+        return Some(state.record_of_repeats(repeats));
+    }
+
+    let mut groups = s.split("; ").filter(|g| !g.is_empty());
+    let records = state
+        .groups
+        .iter()
+        .map(|g| {
+            if g.count == 0 {
+                return Some(Vec::new());
+            }
+            let dice = groups
+                .next()?
+                .split(' ')
+                .map(parse_die)
+                .collect::<Option<Vec<DieRecord>>>()?;
+            if dice.len() == g.count as usize {
+                Some(dice)
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<Vec<DieRecord>>>>()?;
+    if groups.next().is_some() {
+        return None;
+    }
+    Some(state.record_of(records))
+}
+
+/// `tens 3 5 units 7` for a D100 with a bonus dice.
+fn format_percentile(percentile: &coc::PercentileRoll) -> String {
+    let tens: Vec<String> = percentile.tens.iter().map(|t| t.to_string()).collect();
+    format!("tens {} units {}", tens.join(" "), percentile.units)
+}
+
+fn parse_percentile(s: &str, bonus: i32) -> Option<coc::PercentileRoll> {
+    let (tens, units) = s.strip_prefix("tens ")?.split_once(" units ")?;
+    let tens = tens
+        .split(' ')
+        .map(|t| t.parse().ok().filter(|t| (0..10).contains(t)))
+        .collect::<Option<Vec<i32>>>()?;
+    let units = units.parse().ok().filter(|u| (0..10).contains(u))?;
+    if tens.len() != bonus.unsigned_abs() as usize + 1 {
+        return None;
+    }
+    Some(coc::PercentileRoll::new(units, tens, bonus))
+}
+
+/// The kind of the record, what it was rolled with and its dice.
+fn format_body(record: &RollRecord) -> Vec<String> {
+    if let Some(sanity) = &record.sanity {
+        let percentile = record.percentile.as_ref().unwrap();
+        return vec![
+            "sanity".to_string(),
+            sanity.san.to_string(),
+            sanity.success_loss.clone(),
+            sanity.failure_loss.clone(),
+            format_percentile(percentile),
+            sanity
+                .loss_roll
+                .as_ref()
+                .map_or("-".to_string(), |r| format_dice(r)),
+        ];
+    }
+    if let Some(abilities) = &record.abilities {
+        let rolls: Vec<String> = abilities.rolls.iter().map(format_dice).collect();
+        return vec![
+            "abilities".to_string(),
+            abilities.method.notation().to_string(),
+            abilities
+                .min_modifier
+                .map_or("-".to_string(), |m| m.to_string()),
+            abilities.discarded.to_string(),
+            rolls.join(" | "),
+        ];
+    }
+    match (&record.percentile, &record.check) {
+        (Some(percentile), Some(check)) => vec![
+            "check".to_string(),
+            check.skill.to_string(),
+            percentile.bonus.to_string(),
+            format_percentile(percentile),
+        ],
+        (Some(percentile), None) => vec![
+            "d100".to_string(),
+            percentile.bonus.to_string(),
+            format_percentile(percentile),
+        ],
+        (None, _) => vec![
+            "dice".to_string(),
+            record.state.gen_description(),
+            format_dice(record),
+        ],
+    }
+}
+
+fn parse_body(fields: &[String]) -> Option<Box<RollRecord>> {
+    let int = |i: usize| fields.get(i)?.parse::<i32>().ok();
+    let record = match fields.first()?.as_str() {
+        "dice" => parse_dice(
+            &DicesState::from_notation(fields.get(1)?).ok()?,
+            fields.get(2)?,
+        )?,
+        "d100" => parse_percentile(fields.get(2)?, int(1)?)?.into_record(),
+        "check" => coc::SkillCheck::record(int(1)?, parse_percentile(fields.get(3)?, int(2)?)?),
+        "sanity" => {
+            let (san, success_loss, failure_loss) = (int(1)?, fields.get(2)?, fields.get(3)?);
+            let percentile = parse_percentile(fields.get(4)?, 0)?;
+            let losses = coc::SanityCheck::parse_losses(success_loss, failure_loss).ok()?;
+            let level = coc::SuccessLevel::of(percentile.value(), san);
+            let loss_roll = match (coc::SanityCheck::loss_dice(level, &losses), fields.get(5)?) {
+                (None, s) if s == "-" => None,
+                (Some(state), s) => Some(parse_dice(state, s)?),
+                (None, _) => return None,
+            };
+            coc::SanityCheck::record(san, success_loss, failure_loss, percentile, loss_roll).ok()?
+        }
+        "abilities" => {
+            let method = dnd::AbilityMethod::from_notation(fields.get(1)?)?;
+            let state = method.state();
+            let rolls = fields
+                .get(4)?
+                .split(" | ")
+                .map(|r| parse_dice(&state, r).map(|r| *r))
+                .collect::<Option<Vec<RollRecord>>>()?;
+            dnd::AbilityScores::record(dnd::AbilityScores {
+                rolls,
+                discarded: fields.get(3)?.parse().ok()?,
+                method,
+                min_modifier: match fields.get(2)?.as_str() {
+                    "-" => None,
+                    _ => Some(int(2)?),
+                },
+            })
+        }
+        _ => return None,
+    };
+    Some(record)
+}
+
+fn format_line(record: &RollRecord) -> String {
+    let is_checked_otherwise = record.replay.is_some() || record.fair.is_some();
+    let tape: Vec<String> = if is_checked_otherwise || record.tape.len() > TAPE_LIMIT {
+        Vec::new()
+    } else {
+        record.tape.iter().map(|v| v.to_string()).collect()
+    };
+    let mut fields = vec![
+        record.time.to_rfc3339(),
+        record.description.clone(),
+        record.label.clone().unwrap_or_default(),
+        format_origin(record),
+        tape.join(" "),
+        record.total.to_string(),
+    ];
+    fields.extend(format_body(record));
+    let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+    fields.join("\t")
+}
+
+/// The record as saved, and whether rolling it again on its numbers or from its seed
+/// gives the same.
+fn parse_line(line: &str) -> Option<(Box<RollRecord>, bool)> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect();
+    let time = chrono::DateTime::parse_from_rfc3339(fields.first()?).ok()?;
+    let tape = fields
+        .get(4)?
+        .split_whitespace()
+        .map(|v| v.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let total: i32 = fields.get(5)?.parse().ok()?;
+    let body = fields.get(6..)?;
+
+    let mut record = parse_body(body)?;
+    parse_origin(&fields[3], &mut record)?;
+    let replayed = if !tape.is_empty() {
+        Some(record.rolled_again(&mut Tape(tape.iter())))
+    } else if record.replay.is_some() {
+        Some(record.replayed())
+    } else {
+        None
+    };
+    let is_same = record.total == total
+        && match replayed {
+            Some(Some(r)) => r.total == total && format_body(&r) == body,
+            Some(None) => false,
+            None => true,
+        };
+
+    record.total = total;
+    record.time = time.with_timezone(&chrono::Local);
+    record.description = fields[1].clone();
+    record.label = match fields[2].as_str() {
+        "" => None,
+        label => Some(label.to_string()),
+    };
+    record.tape = tape;
+    Some((record, is_same))
+}
+
+/// A history file as read.
+pub struct Log {
+    /// Of the session the file is for.
+    pub name: Option<String>,
+    pub records: Vec<RollRecord>,
+    /// Lines that could not be read, like one cut short by a crash.
+    pub skipped: usize,
+    /// Records that no longer roll the same on their numbers. They are kept as saved.
+    pub changed: usize,
+}
+
+impl Log {
+    /// What went wrong while reading the file at `path`, if anything.
+    pub fn notice(&self, path: &Path) -> Option<String> {
+        let mut notices = Vec::new();
+        if self.skipped != 0 {
+            notices.push(format!("{} lines could not be read", self.skipped));
+        }
+        if self.changed != 0 {
+            notices.push(format!(
+                "{} rolls do not roll the same again, they are shown as saved",
+                self.changed
+            ));
+        }
+        if notices.is_empty() {
+            None
+        } else {
+            Some(format!("{}: {}.", path.display(), notices.join(", ")))
+        }
+    }
+}
+
+/// Rolls kept in a file at a glance, without reading them.
+pub struct Summary {
+    pub path: PathBuf,
+    pub name: Option<String>,
+    pub rolls: usize,
+    pub first: Option<chrono::DateTime<chrono::Local>>,
+    pub last: Option<chrono::DateTime<chrono::Local>>,
+}
+
+/// The name line and the roll lines, `None` if `content` is not a history.
+fn split_lines(content: &str) -> Option<(Option<String>, Vec<&str>)> {
+    let mut lines = content.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+    let mut name = None;
+    let rolls = lines
+        .filter(|line| match line.split_once('\t') {
+            Some((SESSION, n)) => {
+                name = Some(unescape(n));
+                false
+            }
+            _ => !line.is_empty(),
+        })
+        .collect();
+    Some((name, rolls))
+}
+
+fn parse_log(content: &str, path: &Path, max: usize) -> Result<Log, String> {
+    let (name, lines) =
+        split_lines(content).ok_or(format!("{} is not in a known format", path.display()))?;
+    let mut log = Log {
+        name,
+        records: Vec::new(),
+        skipped: 0,
+        changed: 0,
+    };
+    for line in lines.iter().rev() {
+        if log.records.len() >= max {
+            break;
+        }
+        match parse_line(line) {
+            Some((record, is_same)) => {
+                log.records.push(*record);
+                if !is_same {
+                    log.changed += 1;
+                }
+            }
+            None => log.skipped += 1,
+        }
+    }
+    log.records.reverse();
+    Ok(log)
+}
+
+/// The last `max` records in the file at `path`, oldest first.
+pub fn read(path: &Path, max: usize) -> Result<Log, String> {
+    let content = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_log(&String::from_utf8_lossy(&content), path, max)
+}
+
+/// `None` if there is no history at `path`.
+pub fn summarize(path: &Path) -> Option<Summary> {
+    let content = std::fs::read(path).ok()?;
+    let content = String::from_utf8_lossy(&content);
+    let (name, lines) = split_lines(&content)?;
+    let time = |line: Option<&&str>| {
+        let time = line?.split('\t').next()?;
+        let time = chrono::DateTime::parse_from_rfc3339(time).ok()?;
+        Some(time.with_timezone(&chrono::Local))
+    };
+    Some(Summary {
+        path: path.to_path_buf(),
+        name,
+        rolls: lines.len(),
+        first: time(lines.first()),
+        last: time(lines.last()),
+    })
+}
+
+/// Whether the file at `path` is a history of any version, which must not be written
+/// over.
+pub fn is_history(path: &Path) -> bool {
+    let mut head = Vec::new();
+    match std::fs::File::open(path) {
+        Ok(file) => {
+            file.take(FORMAT.len() as u64)
+                .read_to_end(&mut head)
+                .is_ok()
+                && head == FORMAT.as_bytes()
+        }
+        Err(_) => false,
+    }
+}
+
+/// The header lines and at least the last `lines` lines, read from the end in pieces, so
+/// opening takes as long however many rolls the file has and however long they are.
+fn read_tail(file: &mut std::fs::File, lines: usize) -> std::io::Result<String> {
+    let mut start = file.metadata()?.len();
+    let mut content = Vec::new();
+    let mut newlines = 0;
+    // One more, as the first line read is most likely cut.
+    while start > 0 && newlines <= lines {
+        let from = start.saturating_sub(READ_CHUNK);
+        let mut chunk = Vec::new();
+        file.seek(SeekFrom::Start(from))?;
+        (&mut *file).take(start - from).read_to_end(&mut chunk)?;
+        newlines += chunk.iter().filter(|&&b| b == b'\n').count();
+        chunk.extend(content);
+        content = chunk;
+        start = from;
+    }
+    if start == 0 {
+        return Ok(String::from_utf8_lossy(&content).into_owned());
+    }
+
+    let tail = content;
+    let mut content = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    (&mut *file).take(HEAD_LIMIT).read_to_end(&mut content)?;
+    let head = String::from_utf8_lossy(&content).into_owned();
+    let mut text: String = head
+        .lines()
+        .take(2)
+        .filter(|line| *line == HEADER || line.starts_with(SESSION))
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    let tail = String::from_utf8_lossy(&tail);
+    if let Some((_, rest)) = tail.split_once('\n') {
+        text.push_str(rest);
+    }
+    Ok(text)
+}
+
+/// The history file, appended to on every roll.
+#[derive(Default)]
+pub struct History {
+    file: Option<std::fs::File>,
+    path: PathBuf,
+    /// Why the history is not being saved, or what could not be read from it.
+    pub error: Option<String>,
+}
+
+impl History {
+    /// Opens the file at `path` with the last `max` records in it, oldest first. A new
+    /// file is made for the session `name`, if given. A line that cannot be read, like
+    /// one cut short by a crash, is skipped and counted in the error. Outside of a
+    /// session, a file grown past [`ROTATE_LIMIT`] is rotated.
+    pub fn open(path: &Path, name: Option<&str>, max: usize) -> (History, Vec<RollRecord>) {
+        match History::try_open(path, name, max) {
+            Ok((file, log)) => (
+                History {
+                    file: Some(file),
+                    path: path.to_path_buf(),
+                    error: log.notice(path),
+                },
+                log.records,
+            ),
+            Err(e) => (
+                History {
+                    file: None,
+                    path: path.to_path_buf(),
+                    error: Some(format!("History is not saved: {}", e)),
+                },
+                Vec::new(),
+            ),
+        }
+    }
+
+    fn try_open(
+        path: &Path,
+        name: Option<&str>,
+        max: usize,
+    ) -> Result<(std::fs::File, Log), String> {
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(error)?;
+
+        let content = read_tail(&mut file, max).map_err(error)?;
+        if content.is_empty() {
+            History::write_header(&mut file, name).map_err(error)?;
+            let log = Log {
+                name: name.map(str::to_string),
+                records: Vec::new(),
+                skipped: 0,
+                changed: 0,
+            };
+            return Ok((file, log));
+        }
+
+        let log = parse_log(&content, path, max)?;
+        let len = file.metadata().map_err(error)?.len();
+        if name.is_none() && len > ROTATE_LIMIT {
+            drop(file);
+            let file = History::rotate(path, &log).map_err(error)?;
+            return Ok((file, log));
+        }
+        if !content.ends_with('\n') {
+            // The last roll was cut short, the next one starts on its own line.
+            writeln!(file).map_err(error)?;
+        }
+        Ok((file, log))
+    }
+
+    fn write_header(file: &mut std::fs::File, name: Option<&str>) -> std::io::Result<()> {
+        writeln!(file, "{}", HEADER)?;
+        if let Some(name) = name {
+            writeln!(file, "{}\t{}", SESSION, escape(name))?;
+        }
+        Ok(())
+    }
+
+    /// Moves the file at `path` aside and starts it again with the records of `log`. An
+    /// older file moved aside is never written over.
+    fn rotate(path: &Path, log: &Log) -> std::io::Result<std::fs::File> {
+        let old = (1..)
+            .map(|i| path.with_file_name(format!("history.old.{}.txt", i)))
+            .find(|old| !old.exists())
+            .unwrap();
+        std::fs::rename(path, old)?;
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        History::write_header(&mut file, None)?;
+        for record in log.records.iter() {
+            writeln!(file, "{}", format_line(record))?;
+        }
+        file.sync_data()?;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Written and flushed to the disk before the roll is shown.
+    pub fn append(&mut self, record: &RollRecord) {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let written = file
+            .write_all(format!("{}\n", format_line(record)).as_bytes())
+            .and_then(|_| file.sync_data());
+        if let Err(e) = written {
+            self.error = Some(format!(
+                "History is not saved: {}: {}",
+                self.path.display(),
+                e
+            ));
+            self.file = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rng::DiceRng;
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Parses the line of `record`, checking it is written the same again.
+    fn round_trip(record: &RollRecord) -> (Box<RollRecord>, bool) {
+        let line = format_line(record);
+        let (parsed, is_same) = parse_line(&line).unwrap_or_else(|| panic!("{}", line));
+        assert_eq!(format_line(&parsed), line);
+        assert_eq!(parsed.total, record.total);
+        assert_eq!(parsed.result_text(), record.result_text());
+        assert_eq!(parsed.description, record.description);
+        assert_eq!(parsed.label, record.label);
+        assert_eq!(parsed.time, record.time);
+        (parsed, is_same)
+    }
+
+    fn roll(notation: &str) -> Box<RollRecord> {
+        let state = DicesState::from_notation(notation).unwrap();
+        DiceRng::new().replayable(|rd| state.roll(rd))
+    }
+
+    #[test]
+    fn plain_roll() {
+        for notation in [
+            "3d6+2",
+            "4d6dl1",
+            "5d6!",
+            "4d6r1",
+            "8d10>=8f1",
+            "3#1d20+5 >= 13",
+        ] {
+            let record = roll(notation);
+            assert!(!record.tape.is_empty());
+            let (parsed, is_same) = round_trip(&record);
+            assert!(is_same, "{}", notation);
+            assert_eq!(parsed.tape, record.tape);
+        }
+    }
+
+    #[test]
+    fn long_tape_is_not_kept() {
+        let record = roll("200d6");
+        let (parsed, is_same) = round_trip(&record);
+        assert!(is_same);
+        assert!(parsed.tape.is_empty());
+    }
+
+    #[test]
+    fn seeded_roll() {
+        let state = DicesState::from_notation("2d20kh1+3").unwrap();
+        let replay = Replay {
+            seed: 42,
+            word_pos: 0,
+        };
+        let mut record = state.roll(&mut rand_chacha::ChaCha20Rng::seed_from_u64(42));
+        record.replay = Some(replay);
+        let (parsed, is_same) = round_trip(&record);
+        assert!(is_same);
+        assert_eq!(parsed.replay.map(|r| r.seed), Some(42));
+
+        // Another seed would not have rolled these dice.
+        record.replay = Some(Replay {
+            seed: 43,
+            word_pos: 0,
+        });
+        let (_, is_same) = parse_line(&format_line(&record)).unwrap();
+        assert!(!is_same);
+    }
+
+    #[test]
+    fn fair_roll() {
+        let mut record = roll("1d20+5");
+        record.fair = Some(FairRoll {
+            commitment: "0123456789abcdef".repeat(4),
+            player_seed: "the players\tchose this".to_string(),
+            nonce: 7,
+        });
+        let (parsed, is_same) = round_trip(&record);
+        assert!(is_same);
+        let fair = parsed.fair.unwrap();
+        assert_eq!(fair.nonce, 7);
+        assert_eq!(fair.player_seed, "the players\tchose this");
+        assert!(parsed.tape.is_empty());
+    }
+
+    #[test]
+    fn labelled_roll() {
+        let mut record = roll("1d20+5");
+        record.label = Some("Spot\\Hidden\t2".to_string());
+        record.description = "Spot Hidden\n1D20 + 5".to_string();
+        assert!(round_trip(&record).1);
+    }
+
+    #[test]
+    fn coc_check() {
+        let record = DiceRng::new().replayable(|rd| coc::SkillCheck::roll(60, 1, 0, rd));
+        let (parsed, is_same) = round_trip(&record);
+        assert!(is_same);
+        let check = parsed.check.unwrap();
+        assert_eq!(check.skill, 60);
+        assert!(check.level == record.check.as_ref().unwrap().level);
+        assert_eq!(parsed.percentile.unwrap().tens.len(), 2);
+    }
+
+    #[test]
+    fn tail_of_a_long_file() {
+        let path = std::env::temp_dir().join(format!("dice_redo_tail_{}.txt", std::process::id()));
+        let lines: Vec<String> = (0..400).map(|_| format_line(&roll("300d6"))).collect();
+        let content = format!(
+            "{}\n{}\t{}\n{}\n",
+            HEADER,
+            SESSION,
+            "long",
+            lines.join("\n")
+        );
+        std::fs::write(&path, content).unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let tail = read_tail(&mut file, 100).unwrap();
+        let log = parse_log(&tail, &path, 100).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(log.name.as_deref(), Some("long"));
+        assert_eq!((log.records.len(), log.skipped), (100, 0));
+        assert_eq!(format_line(&log.records[99]), lines[399]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let line = format_line(&roll("3d6"));
+        for cut in [10, line.len() / 2, line.len() - 1] {
+            assert!(parse_line(&line[..cut]).is_none(), "{}", &line[..cut]);
+        }
+        let mut fields: Vec<&str> = line.split('\t').collect();
+        fields[3] = "fair 1 abc players";
+        assert!(parse_line(&fields.join("\t")).is_none());
+        assert!(parse_line("").is_none());
+        assert!(parse_line("not a roll").is_none());
+
+        // Other numbers than the dice were rolled on: kept, but reported.
+        let mut fields: Vec<&str> = line.split('\t').collect();
+        fields[4] = "1 2 3";
+        assert!(!parse_line(&fields.join("\t")).unwrap().1);
+
+        let content = format!("{}\n{}\ngarbage\n{}", HEADER, line, &line[..20]);
+        let log = parse_log(&content, Path::new("history.txt"), 100).unwrap();
+        assert_eq!((log.records.len(), log.skipped, log.changed), (1, 2, 0));
+        assert!(parse_log("dice_redo history 1\n", Path::new("history.txt"), 100).is_err());
+    }
+}
@@ -0,0 +1,420 @@
+//! Who is cursed: how the recorded dice fell compared to fair ones.
+
+use super::{Compare, CompareOp, RecordLine, RollRecord};
+use eframe::egui;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Rolls in the moving average of the luck chart.
+const LUCK_WINDOW: usize = 10;
+/// The chi-squared test needs about this many rolls of every face to be trusted.
+const MIN_EXPECTED: f64 = 5.0;
+
+/// Times every face of a die came up, `counts[0]` being face 1.
+struct Faces {
+    sides: i32,
+    counts: Vec<u64>,
+}
+
+impl Faces {
+    fn rolls(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    fn expected(&self) -> f64 {
+        self.rolls() as f64 / self.sides as f64
+    }
+
+    fn mean(&self) -> f64 {
+        let sum: u64 = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| (i as u64 + 1) * c)
+            .sum();
+        sum as f64 / self.rolls() as f64
+    }
+
+    /// Pearson's chi-squared against a fair die, and its p-value.
+    fn chi_squared(&self) -> (f64, f64) {
+        let expected = self.expected();
+        let chi2: f64 = self
+            .counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum();
+        let df = (self.sides - 1) as f64;
+        (chi2, gamma_q(df / 2.0, chi2 / 2.0))
+    }
+}
+
+/// Adds every face rolled for `record` to `faces`, by the sides of the die. Dropped and
+/// rerolled dice count too, each of them was a fair roll.
+fn count_faces(record: &RollRecord, faces: &mut BTreeMap<i32, Faces>) {
+    let mut add = |sides: i32, face: i32| {
+        if sides < 2 || face < 1 || face > sides {
+            return;
+        }
+        let f = faces.entry(sides).or_insert_with(|| Faces {
+            sides,
+            counts: vec![0; sides as usize],
+        });
+        f.counts[face as usize - 1] += 1;
+    };
+
+    match &record.percentile {
+        // With bonus or penalty dice the value kept is not a fair D100.
+        Some(percentile) if percentile.tens.len() == 1 => add(100, percentile.value()),
+        Some(_) => {}
+        None => {
+            for (g, dice) in record.state.groups.iter().zip(&record.records) {
+                for die in dice {
+                    for &face in die.rerolled.iter().chain(&die.rolls) {
+                        add(g.sides, face);
+                    }
+                }
+            }
+        }
+    }
+
+    let loss_roll = record.sanity.as_ref().and_then(|s| s.loss_roll.as_deref());
+    let abilities = record.abilities.iter().flat_map(|a| a.rolls.iter());
+    for r in record.repeats.iter().chain(abilities).chain(loss_roll) {
+        count_faces(r, faces);
+    }
+}
+
+/// Percentile of every roll whose distribution is known, oldest first. It is turned
+/// around for a roll against a target like `<= 5`, where a low total is the lucky one.
+fn luck(table: &VecDeque<RecordLine>) -> Vec<(&RollRecord, f64)> {
+    let mut luck = Vec::new();
+    for line in table.iter() {
+        let record: &RollRecord = &line.record;
+        let rolls = if record.repeats.is_empty() {
+            std::slice::from_ref(record)
+        } else {
+            &record.repeats[..]
+        };
+        for r in rolls {
+            if let Some(dist) = &r.distribution {
+                let percentile = dist.percentile(r.total);
+                let percentile = match r.state.target {
+                    Some(Compare {
+                        op: CompareOp::Lt | CompareOp::Le,
+                        ..
+                    }) => 100.0 - percentile,
+                    _ => percentile,
+                };
+                luck.push((record, percentile));
+            }
+        }
+    }
+    luck
+}
+
+/// The longest run of rolls above or below the middle.
+struct Streak {
+    len: usize,
+    from: chrono::DateTime<chrono::Local>,
+    to: chrono::DateTime<chrono::Local>,
+}
+
+fn longest_streak(luck: &[(&RollRecord, f64)], is_in: impl Fn(f64) -> bool) -> Option<Streak> {
+    let mut longest: Option<Streak> = None;
+    let mut start = 0;
+    for (i, &(_, p)) in luck.iter().enumerate() {
+        if !is_in(p) {
+            start = i + 1;
+            continue;
+        }
+        let len = i + 1 - start;
+        let is_longer = match &longest {
+            Some(s) => len > s.len,
+            None => true,
+        };
+        if is_longer {
+            longest = Some(Streak {
+                len,
+                from: luck[start].0.time,
+                to: luck[i].0.time,
+            });
+        }
+    }
+    longest
+}
+
+/// ln Γ(x) for x > 0, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const C: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let sum = C[1..]
+        .iter()
+        .enumerate()
+        .fold(C[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+    let t = x + G + 0.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// The regularized upper incomplete gamma function Q(a, x), which gives the p-value of
+/// chi-squared `2x` with `2a` degrees of freedom.
+fn gamma_q(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-14;
+    const MAX_ITERATIONS: usize = 500;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let ln_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // The series of P(a, x).
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term < sum * EPSILON {
+                break;
+            }
+        }
+        (1.0 - sum * ln_prefix.exp()).max(0.0)
+    } else {
+        // The continued fraction of Q(a, x), by the modified Lentz method.
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for n in 1..MAX_ITERATIONS {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (ln_prefix.exp() * h).min(1.0)
+    }
+}
+
+/// What the p-value says about a die, with its color.
+fn verdict(faces: &Faces, p: f64) -> (&'static str, egui::Color32) {
+    if faces.expected() < MIN_EXPECTED {
+        ("too few rolls", egui::Color32::GRAY)
+    } else if p < 0.01 {
+        ("cursed?", egui::Color32::RED)
+    } else if p < 0.05 {
+        ("doubtful", egui::Color32::from_rgb(230, 120, 0))
+    } else {
+        ("fair", egui::Color32::DARK_GREEN)
+    }
+}
+
+pub struct LuckWindow {
+    pub is_show: bool,
+
+    /// Sides of the die whose faces are charted.
+    sides: Option<i32>,
+}
+
+impl LuckWindow {
+    pub fn new() -> LuckWindow {
+        LuckWindow {
+            is_show: false,
+            sides: None,
+        }
+    }
+
+    /// Of the records in the table, which are the current session.
+    pub fn update(&mut self, table: &VecDeque<RecordLine>, ctx: &egui::CtxRef) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Statistics")
+            .default_width(420.0)
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let mut faces = BTreeMap::new();
+                    for line in table.iter() {
+                        count_faces(&line.record, &mut faces);
+                    }
+                    ui.heading("Faces");
+                    self.show_faces(ui, &faces);
+
+                    ui.separator();
+                    ui.heading("Luck");
+                    LuckWindow::show_luck(ui, &luck(table));
+                });
+            });
+        self.is_show = is_show;
+    }
+
+    fn show_faces(&mut self, ui: &mut egui::Ui, faces: &BTreeMap<i32, Faces>) {
+        if faces.is_empty() {
+            ui.label("No dice rolled yet.");
+            return;
+        }
+        if !matches!(self.sides, Some(s) if faces.contains_key(&s)) {
+            self.sides = faces.keys().next().copied();
+        }
+
+        egui::Grid::new("luck_faces").striped(true).show(ui, |ui| {
+            ui.strong("Die");
+            ui.strong("Rolls");
+            ui.strong("Mean");
+            ui.strong("χ²");
+            ui.strong("p")
+                .on_hover_text("The chance of a fair die being at least this uneven.");
+            ui.end_row();
+            for f in faces.values() {
+                let (chi2, p) = f.chi_squared();
+                let (verdict, color) = verdict(f, p);
+                ui.selectable_value(&mut self.sides, Some(f.sides), format!("D{}", f.sides));
+                ui.label(f.rolls().to_string());
+                ui.label(format!(
+                    "{:.2} ({:.1})",
+                    f.mean(),
+                    (f.sides + 1) as f64 / 2.0
+                ));
+                ui.label(format!("{:.1} / {}", chi2, f.sides - 1));
+                ui.label(format!("{:.3}", p));
+                ui.label(egui::RichText::new(verdict).strong().color(color));
+                ui.end_row();
+            }
+        });
+
+        let f = match self.sides.and_then(|s| faces.get(&s)) {
+            Some(f) => f,
+            None => return,
+        };
+        use egui::plot::{Bar, BarChart, HLine, Legend, Plot};
+        let bars = f
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| Bar::new(i as f64 + 1.0, c as f64).width(0.9))
+            .collect();
+        Plot::new(("luck_faces_chart", f.sides))
+            .height(160.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .include_y(0.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(
+                    BarChart::new(bars)
+                        .color(egui::Color32::LIGHT_BLUE)
+                        .name(format!("D{} rolled", f.sides)),
+                );
+                plot_ui.hline(
+                    HLine::new(f.expected())
+                        .color(egui::Color32::RED)
+                        .name("fair"),
+                );
+            });
+    }
+
+    fn show_luck(ui: &mut egui::Ui, luck: &[(&RollRecord, f64)]) {
+        if luck.is_empty() {
+            ui.label("No roll with known odds yet.");
+            return;
+        }
+        let mean = luck.iter().map(|(_, p)| p).sum::<f64>() / luck.len() as f64;
+        ui.label(format!(
+            "Average percentile p{:.0} over {} rolls.",
+            mean,
+            luck.len()
+        ));
+
+        let time = |t: chrono::DateTime<chrono::Local>| t.format("%H:%M:%S").to_string();
+        egui::Grid::new("luck_streaks").show(ui, |ui| {
+            let streaks = [
+                ("Longest high streak", longest_streak(luck, |p| p > 50.0)),
+                ("Longest low streak", longest_streak(luck, |p| p < 50.0)),
+            ];
+            for (name, streak) in streaks {
+                ui.strong(name);
+                match streak {
+                    Some(s) => ui.label(format!(
+                        "{} rolls ({} ~ {})",
+                        s.len,
+                        time(s.from),
+                        time(s.to)
+                    )),
+                    None => ui.label("none"),
+                };
+                ui.end_row();
+            }
+        });
+
+        use egui::plot::{HLine, Legend, Line, Plot, Points, Value, Values};
+        let points: Vec<Value> = luck
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, p))| Value::new(i as f64 + 1.0, p))
+            .collect();
+        let mut sum = 0.0;
+        let average: Vec<Value> = luck
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, p))| {
+                sum += p;
+                Value::new(i as f64 + 1.0, sum / (i + 1) as f64)
+            })
+            .collect();
+        let moving: Vec<Value> = (0..luck.len())
+            .map(|i| {
+                let window = &luck[(i + 1).saturating_sub(LUCK_WINDOW)..=i];
+                let p = window.iter().map(|(_, p)| p).sum::<f64>() / window.len() as f64;
+                Value::new(i as f64 + 1.0, p)
+            })
+            .collect();
+        Plot::new("luck_chart")
+            .height(180.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .include_y(0.0)
+            .include_y(100.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.points(
+                    Points::new(Values::from_values(points))
+                        .color(egui::Color32::GRAY)
+                        .radius(1.5)
+                        .name("roll"),
+                );
+                plot_ui.line(
+                    Line::new(Values::from_values(moving))
+                        .color(egui::Color32::LIGHT_BLUE)
+                        .name(format!("last {}", LUCK_WINDOW)),
+                );
+                plot_ui.line(
+                    Line::new(Values::from_values(average))
+                        .color(egui::Color32::GOLD)
+                        .name("average"),
+                );
+                plot_ui.hline(HLine::new(50.0).color(egui::Color32::DARK_GRAY));
+            });
+    }
+}
@@ -0,0 +1,730 @@
+//! Parser for the usual TRPG dice notation, e.g. `3d6+2` or `1d20 + 1d4 - 1`.
+
+use super::expr::{BinaryOp, Expr, Rounding};
+use super::{Compare, CompareOp, DiceGroup, ExplodeRule, KeepRule, PoolRule, RerollRule};
+use std::fmt;
+
+pub const MAX_DICE_COUNT: i32 = 1000;
+pub const MAX_DICE_SIDES: i32 = 10000;
+pub const MAX_CONSTANT: i32 = 1_000_000;
+pub const MAX_REPEAT: i32 = 100;
+/// Parentheses, functions and signs nested deeper than this are refused.
+pub const MAX_NESTING: usize = 64;
+
+/// A parsed expression.
+pub struct Notation {
+    /// Every dice in the expression, in order.
+    pub groups: Vec<DiceGroup>,
+    pub expr: Expr,
+    /// A comparison at the end of the whole expression.
+    pub target: Option<Compare>,
+    /// Times to roll the whole expression, 1 without a `N#` prefix.
+    pub repeat: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    /// Char index (0-based) where the problem was found.
+    pub position: usize,
+    pub reason: String,
+}
+
+impl ParseError {
+    pub fn new(position: usize, reason: impl Into<String>) -> ParseError {
+        ParseError {
+            position,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.position + 1, self.reason)
+    }
+}
+
+/// Parse `expression` into its dice and the arithmetic on them.
+///
+/// Accepts `XdY` (`X` defaults to 1, `d%` means `d100`), plain integers, `+`, `-`, `*`
+/// and `/` between terms, signs, parentheses and any whitespace. `d` is case-insensitive.
+/// Division is exact, and `floor(...)`, `ceil(...)` or `round(...)` round the value
+/// inside; the final total is rounded down. Dividing by a value that is 0 without
+/// rolling, like `/0` or `/(1-1)`, is an error; dividing by dice that roll 0, like
+/// `/(1d2-1)`, gives 0.
+///
+/// A dice may be followed by modifiers, in any order:
+/// * a keep/drop rule: `kh`/`kl` keep the highest/lowest, `dh`/`dl` drop the
+///   highest/lowest, e.g. `4d6kh3`, `2d20kl1`, `4d6dl1`. The number of dice
+///   defaults to 1, and a bare `k` means `kh`.
+/// * an explosion: `!` rolls the dice again on the max face and adds the new roll to it,
+///   so a keep/drop rule keeps or drops the whole chain. `!!` does the same, but a pool
+///   counts the sum of the chain as one face instead of every roll on its own. A
+///   comparison such as `!>=9` changes which faces explode.
+/// * rerolls: `r` rolls a dice again until it no longer matches, `ro` only once. The
+///   face is a number or a comparison and can be repeated, e.g. `r1`, `ro<=2`, `r1r2`.
+/// * a success target, which turns the dice into a pool counting the dice meeting it,
+///   e.g. `8d10>=8`. It may be followed by `db` for faces counting two successes, `f`
+///   for faces cancelling one and `g` for the glitch check: `8d10>=8db10f1`, `12d6>=5g`.
+///
+/// A comparison that is not right after a dice compares the whole total with a target,
+/// e.g. `1d20+5 >= 15`, `1d6-5 >= -2` or `8d10>=8 >= 3`. So does a success target on
+/// the only dice of the expression, when it is a single one: `1d20>=15` is `1d20 >= 15`.
+///
+/// `N#` in front rolls everything `N` times, e.g. `6#4d6dl1` or `3#1d20+5 >= 13`.
+pub fn parse(expression: &str) -> Result<Notation, ParseError> {
+    let mut parser = Parser::new(expression);
+    let repeat = parser.parse_repeat()?;
+    let (expr, target) = parser.parse_expression()?;
+    Ok(Notation {
+        groups: parser.groups,
+        expr,
+        target,
+        repeat,
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+    groups: Vec<DiceGroup>,
+}
+
+impl Parser {
+    fn new(expression: &str) -> Parser {
+        Parser {
+            chars: expression.chars().collect(),
+            pos: 0,
+            depth: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn describe_current(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("'{}'", c),
+            None => "end of input".to_string(),
+        }
+    }
+
+    /// Parse an optional `N#` prefix.
+    fn parse_repeat(&mut self) -> Result<i32, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let digits = self.chars[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let spaces = self.chars[start + digits..]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        if digits == 0 || self.chars.get(start + digits + spaces) != Some(&'#') {
+            return Ok(1);
+        }
+
+        let repeat = self.parse_number(MAX_REPEAT)?;
+        if repeat == 0 {
+            return Err(ParseError::new(start, "repeat count must be at least 1"));
+        }
+        self.skip_whitespace();
+        self.pos += 1;
+        Ok(repeat)
+    }
+
+    fn parse_expression(&mut self) -> Result<(Expr, Option<Compare>), ParseError> {
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return Err(ParseError::new(self.pos, "expression is empty"));
+        }
+
+        let expr = self.parse_sum()?;
+        let target = self.parse_compare(true)?;
+        if target.is_some() {
+            self.skip_whitespace();
+        }
+        match self.peek() {
+            None => {
+                let target = target.or_else(|| self.single_dice_target(&expr));
+                Ok((expr, target))
+            }
+            Some(_) if target.is_some() => Err(ParseError::new(
+                self.pos,
+                format!(
+                    "expected the end after the target, found {}",
+                    self.describe_current()
+                ),
+            )),
+            Some(')') => Err(ParseError::new(self.pos, "unmatched ')'")),
+            Some(_) => Err(ParseError::new(
+                self.pos,
+                format!(
+                    "expected '+', '-', '*' or '/', found {}",
+                    self.describe_current()
+                ),
+            )),
+        }
+    }
+
+    /// The target of a whole expression that is one dice with a success target, like
+    /// `1d20>=15`. As a pool it could only count 0 or 1 success and would lose the margin.
+    fn single_dice_target(&mut self, expr: &Expr) -> Option<Compare> {
+        let group = match expr {
+            Expr::Dice(i) => &mut self.groups[*i],
+            _ => return None,
+        };
+        match group.pool {
+            Some(PoolRule {
+                success,
+                double: None,
+                failure: None,
+                glitch: false,
+            }) if group.count == 1 => {
+                group.pool = None;
+                Some(success)
+            }
+            _ => None,
+        }
+    }
+
+    /// Terms joined by `+` and `-`.
+    fn parse_sum(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_product()?;
+        loop {
+            self.skip_whitespace();
+            let op = match self.peek() {
+                Some('+') => BinaryOp::Add,
+                Some('-') => BinaryOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let rhs = self.parse_product()?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+    }
+
+    /// Terms joined by `*` and `/`.
+    fn parse_product(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let op = match self.peek() {
+                Some('*') => BinaryOp::Mul,
+                Some('/') => BinaryOp::Div,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            let groups = self.groups.len();
+            let rhs = self.parse_unary()?;
+            // Without dice the divisor is known now, like in `/0` or `/(1-1)`.
+            let is_constant = self.groups.len() == groups;
+            if op == BinaryOp::Div && is_constant && rhs.eval(&[], &mut Vec::new()) == 0.0 {
+                return Err(ParseError::new(start, "division by zero"));
+            }
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::new(self.pos, "expression is nested too deeply"));
+        }
+        self.depth += 1;
+        let expr = match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                self.parse_unary().map(|e| Expr::Neg(Box::new(e)))
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.parse_unary()
+            }
+            _ => self.parse_primary(),
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    /// A number, a dice, or an expression in parentheses, maybe after a function name.
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => self.parse_parenthesized(),
+            Some(c) if c.is_alphabetic() && !matches!(c, 'd' | 'D') => {
+                while matches!(self.peek(), Some(c) if c.is_alphabetic()) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                let rounding = Rounding::from_name(&name).ok_or_else(|| {
+                    ParseError::new(
+                        start,
+                        format!("unknown function '{}', expected floor, ceil or round", name),
+                    )
+                })?;
+                self.skip_whitespace();
+                if self.peek() != Some('(') {
+                    return Err(ParseError::new(
+                        self.pos,
+                        format!("expected '(' after {}", name),
+                    ));
+                }
+                let inner = self.parse_parenthesized()?;
+                Ok(Expr::Round(rounding, Box::new(inner)))
+            }
+            _ => self.parse_term(),
+        }
+    }
+
+    fn parse_parenthesized(&mut self) -> Result<Expr, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        let expr = self.parse_sum()?;
+        self.skip_whitespace();
+        if self.peek() != Some(')') {
+            return Err(ParseError::new(
+                self.pos,
+                format!(
+                    "expected ')' for the '(' at column {}, found {}",
+                    open + 1,
+                    self.describe_current()
+                ),
+            ));
+        }
+        self.pos += 1;
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, ParseError> {
+        let start = self.pos;
+        let count = match self.peek() {
+            Some(c) if c.is_ascii_digit() => Some(self.parse_number(MAX_CONSTANT)?),
+            Some('d' | 'D') => None,
+            _ => {
+                return Err(ParseError::new(
+                    self.pos,
+                    format!(
+                        "expected a number, a dice or '(', found {}",
+                        self.describe_current()
+                    ),
+                ))
+            }
+        };
+
+        if !matches!(self.peek(), Some('d' | 'D')) {
+            return Ok(Expr::Constant(count.unwrap()));
+        }
+        self.pos += 1;
+
+        let count = count.unwrap_or(1);
+        if count == 0 {
+            return Err(ParseError::new(start, "dice count must be at least 1"));
+        }
+        if count > MAX_DICE_COUNT {
+            return Err(ParseError::new(
+                start,
+                format!("at most {} dice can be rolled at once", MAX_DICE_COUNT),
+            ));
+        }
+
+        let sides_pos = self.pos;
+        let sides = match self.peek() {
+            Some('%') => {
+                self.pos += 1;
+                100
+            }
+            Some(c) if c.is_ascii_digit() => self.parse_number(MAX_DICE_SIDES)?,
+            _ => {
+                return Err(ParseError::new(
+                    self.pos,
+                    format!(
+                        "expected the number of sides after 'd', found {}",
+                        self.describe_current()
+                    ),
+                ))
+            }
+        };
+        if sides == 0 {
+            return Err(ParseError::new(sides_pos, "a dice needs at least 1 side"));
+        }
+
+        let mut group = DiceGroup::new(count, sides);
+        self.parse_modifiers(&mut group)?;
+        self.groups.push(group);
+        Ok(Expr::Dice(self.groups.len() - 1))
+    }
+
+    fn parse_modifiers(&mut self, group: &mut DiceGroup) -> Result<(), ParseError> {
+        loop {
+            let start = self.pos;
+            match self.peek() {
+                Some('d' | 'D') if matches!(self.chars.get(self.pos + 1), Some('b' | 'B')) => {
+                    self.pos += 2;
+                    let double = self.parse_face("double")?;
+                    self.pool_rule(group, start, "db", "db10")?.double = Some(double);
+                }
+                Some('k' | 'K' | 'd' | 'D') => {
+                    if group.keep.is_some() {
+                        return Err(ParseError::new(start, "only one keep/drop rule is allowed"));
+                    }
+                    group.keep = Some(self.parse_keep_rule(group.count)?);
+                }
+                Some('!') => {
+                    if group.explode.is_some() {
+                        return Err(ParseError::new(start, "only one explosion is allowed"));
+                    }
+                    group.explode = Some(self.parse_explode_rule(group.sides)?);
+                }
+                Some('r' | 'R') => self.parse_reroll(group)?,
+                Some('>' | '<' | '=') => {
+                    if group.pool.is_some() {
+                        return Err(ParseError::new(start, "only one success target is allowed"));
+                    }
+                    group.pool = self.parse_compare(false)?.map(PoolRule::new);
+                }
+                Some('f' | 'F') => {
+                    self.pos += 1;
+                    let failure = self.parse_face("failure")?;
+                    self.pool_rule(group, start, "f", "f1")?.failure = Some(failure);
+                }
+                Some('g' | 'G') => {
+                    self.pos += 1;
+                    self.pool_rule(group, start, "g", "g")?.glitch = true;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_keep_rule(&mut self, count: i32) -> Result<KeepRule, ParseError> {
+        let start = self.pos;
+        let keep = matches!(self.peek(), Some('k' | 'K'));
+        self.pos += 1;
+
+        let highest = match self.peek() {
+            Some('h' | 'H') => {
+                self.pos += 1;
+                true
+            }
+            Some('l' | 'L') => {
+                self.pos += 1;
+                false
+            }
+            _ if keep => true,
+            _ => {
+                return Err(ParseError::new(
+                    self.pos,
+                    format!(
+                        "expected 'h' or 'l' after 'd', found {}",
+                        self.describe_current()
+                    ),
+                ))
+            }
+        };
+
+        let n = match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.parse_number(MAX_DICE_COUNT)?,
+            _ => 1,
+        };
+        if keep && !(1..=count).contains(&n) {
+            return Err(ParseError::new(
+                start,
+                format!("can only keep 1 to {} of {} dice", count, count),
+            ));
+        }
+        if !keep && !(1..count).contains(&n) {
+            return Err(ParseError::new(
+                start,
+                format!("can only drop 1 to {} of {} dice", count - 1, count),
+            ));
+        }
+
+        Ok(match (keep, highest) {
+            (true, true) => KeepRule::KeepHighest(n),
+            (true, false) => KeepRule::KeepLowest(n),
+            (false, true) => KeepRule::DropHighest(n),
+            (false, false) => KeepRule::DropLowest(n),
+        })
+    }
+
+    fn parse_explode_rule(&mut self, sides: i32) -> Result<ExplodeRule, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let compound = self.peek() == Some('!');
+        if compound {
+            self.pos += 1;
+        }
+
+        let on = self.parse_compare(false)?;
+        let explodes = |v: i32| on.map_or(v == sides, |c| c.matches(v));
+        if (1..=sides).all(explodes) {
+            return Err(ParseError::new(
+                start,
+                format!("every face of D{} would explode", sides),
+            ));
+        }
+
+        Ok(ExplodeRule { on, compound })
+    }
+
+    /// The pool rule a pool-only modifier applies to.
+    fn pool_rule<'a>(
+        &self,
+        group: &'a mut DiceGroup,
+        start: usize,
+        modifier: &str,
+        example: &str,
+    ) -> Result<&'a mut PoolRule, ParseError> {
+        group.pool.as_mut().ok_or_else(|| {
+            ParseError::new(
+                start,
+                format!(
+                    "'{}' needs a success target before it, e.g. 8d10>=8{}",
+                    modifier, example
+                ),
+            )
+        })
+    }
+
+    /// Parse the faces a modifier applies to: a number or a comparison.
+    fn parse_face(&mut self, what: &str) -> Result<Compare, ParseError> {
+        if let Some(c) = self.parse_compare(false)? {
+            return Ok(c);
+        }
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => Ok(Compare {
+                op: CompareOp::Eq,
+                value: self.parse_number(MAX_CONSTANT)?,
+            }),
+            _ => Err(ParseError::new(
+                self.pos,
+                format!(
+                    "expected the {} faces, found {}",
+                    what,
+                    self.describe_current()
+                ),
+            )),
+        }
+    }
+
+    /// Parse one `r`/`ro` clause and add it to the reroll rule of `group`.
+    fn parse_reroll(&mut self, group: &mut DiceGroup) -> Result<(), ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let once = matches!(self.peek(), Some('o' | 'O'));
+        if once {
+            self.pos += 1;
+        }
+
+        let on = self.parse_face("reroll")?;
+
+        let rule = group.reroll.get_or_insert_with(|| RerollRule {
+            on: Vec::new(),
+            once,
+        });
+        if rule.once != once {
+            return Err(ParseError::new(start, "cannot mix 'r' and 'ro'"));
+        }
+        rule.on.push(on);
+
+        let sides = group.sides;
+        if !once && (1..=sides).all(|v| rule.matches(v)) {
+            return Err(ParseError::new(
+                start,
+                format!("every face of D{} would be rerolled", sides),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parse an optional comparison such as `>=9`, `<3` or `=1`, or `>= 9` if `spaced`.
+    fn parse_compare(&mut self, spaced: bool) -> Result<Option<Compare>, ParseError> {
+        let op = match self.peek() {
+            Some('=') => CompareOp::Eq,
+            Some('>') => CompareOp::Gt,
+            Some('<') => CompareOp::Lt,
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        let op = match (op, self.peek()) {
+            (CompareOp::Gt, Some('=')) => CompareOp::Ge,
+            (CompareOp::Lt, Some('=')) => CompareOp::Le,
+            (op, _) => op,
+        };
+        if matches!(op, CompareOp::Ge | CompareOp::Le) {
+            self.pos += 1;
+        }
+        if spaced {
+            self.skip_whitespace();
+        }
+
+        // Totals can be negative, dice faces cannot.
+        let negative = spaced && self.peek() == Some('-');
+        if negative {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let value = self.parse_number(MAX_CONSTANT)?;
+                let value = if negative { -value } else { value };
+                Ok(Some(Compare { op, value }))
+            }
+            _ => Err(ParseError::new(
+                self.pos,
+                format!(
+                    "expected a number after the comparison, found {}",
+                    self.describe_current()
+                ),
+            )),
+        }
+    }
+
+    fn parse_number(&mut self, max: i32) -> Result<i32, ParseError> {
+        let start = self.pos;
+        let mut value: i64 = 0;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            value = value * 10 + digit as i64;
+            if value > max as i64 {
+                return Err(ParseError::new(
+                    start,
+                    format!("number is too large (max {})", max),
+                ));
+            }
+            self.pos += 1;
+        }
+        Ok(value as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::DicesState;
+    use super::*;
+
+    fn description(expression: &str) -> String {
+        match DicesState::from_notation(expression) {
+            Ok(state) => state.gen_description(),
+            Err(e) => panic!("{}: {}", expression, e),
+        }
+    }
+
+    fn error(expression: &str) -> ParseError {
+        match DicesState::from_notation(expression) {
+            Ok(state) => panic!("{} parsed as {}", expression, state.gen_description()),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn sums() {
+        assert_eq!(description("3d6+2"), "3D6 + 2");
+        assert_eq!(description("d%"), "1D100");
+        assert_eq!(description(" 1d20 + 1D4 - 1 "), "1D20 + 1D4 - 1");
+        assert_eq!(description("-2d6+10"), "-2D6 + 10");
+        assert_eq!(description("1d6+2d6"), "1D6 + 2D6");
+    }
+
+    #[test]
+    fn modifiers() {
+        assert_eq!(description("4d6dl1"), "4D6dl1");
+        assert_eq!(description("2d20k"), "2D20kh1");
+        assert_eq!(description("3d6!"), "3D6!");
+        assert_eq!(description("5d10!!>=9"), "5D10!!>=9");
+        assert_eq!(description("4d6r1r2"), "4D6r1r2");
+        assert_eq!(description("2d10ro<=2"), "2D10ro<=2");
+        assert_eq!(description("8d10>=8db10f1"), "8D10>=8db10f1");
+        assert_eq!(description("12d6>=5g"), "12D6>=5g");
+    }
+
+    #[test]
+    fn formulas_and_targets() {
+        assert_eq!(description("(2d6+6)*5"), "(2D6 + 6) * 5");
+        assert_eq!(description("floor(1d6/2)"), "floor(1D6 / 2)");
+        assert_eq!(description("1d20+5 >= 15"), "1D20 + 5 >= 15");
+        assert_eq!(description("1d6-5 >= -2"), "1D6 - 5 >= -2");
+        assert_eq!(description("1d20>=15"), "1D20 >= 15");
+        assert_eq!(description("3#1d100<=45"), "3#1D100 <= 45");
+        assert_eq!(description("1d10>=8f1"), "1D10>=8f1");
+        assert_eq!(description("2d10>=8"), "2D10>=8");
+        assert_eq!(description("6#4d6dl1"), "6#4D6dl1");
+        assert_eq!(description("3#1d20+5 >= 13"), "3#1D20 + 5 >= 13");
+    }
+
+    #[test]
+    fn description_round_trip() {
+        for expression in [
+            "3d6+2",
+            "1d20+1d4-1",
+            "-2d6+10",
+            "4d6dl1",
+            "2d20kl1+3",
+            "3d6!",
+            "5d10!!>=9",
+            "4d6r1r2",
+            "2d10ro<=2",
+            "8d10>=8db10f1",
+            "12d6>=5g",
+            "(2d6+6)*5",
+            "floor(1d6/2)+ceil(1d4/3)",
+            "1d6-(2d4-1)",
+            "1d20+5 >= 15",
+            "1d6-5 >= -2",
+            "8d10>=8 >= 3",
+            "6#4d6dl1",
+            "3#1d20+5 >= 13",
+        ] {
+            let once = description(expression);
+            assert_eq!(description(&once), once, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (expression, position) in [
+            ("", 0),
+            ("1d", 2),
+            ("0d6", 0),
+            ("1d0", 2),
+            ("1001d6", 0),
+            ("1d6+", 4),
+            ("(1d6", 4),
+            ("1d6)", 3),
+            ("1d6 1", 4),
+            ("4d6kh5", 3),
+            ("4d6dl4", 3),
+            ("1d6kh1dl1", 6),
+            ("1d1!", 3),
+            ("1d6r<=6", 3),
+            ("1d6r1ro2", 5),
+            ("1d6f1", 3),
+            ("1d20 >= ", 8),
+            ("1d20 >= 15 + 1", 11),
+            ("sqrt(4)", 0),
+            ("0#1d6", 0),
+            ("101#1d6", 0),
+        ] {
+            assert_eq!(error(expression).position, position, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(error("1d6/0").reason, "division by zero");
+        assert_eq!(error("1d6/(1-1)").reason, "division by zero");
+        assert_eq!(error("1d6/floor(1/2)").reason, "division by zero");
+        // Known only once rolled, it gives 0 then.
+        assert_eq!(description("1d6/(1d2-1)"), "1D6 / (1D2 - 1)");
+    }
+}
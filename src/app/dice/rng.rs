@@ -0,0 +1,366 @@
+//! Where the randomness of every roll comes from.
+
+use super::fair::{FairRoll, FairSession, Revealed};
+use eframe::egui;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RngSource {
+    Thread,
+    Os,
+    Seeded,
+    Fair,
+}
+
+impl RngSource {
+    const ALL: [RngSource; 4] = [
+        RngSource::Thread,
+        RngSource::Os,
+        RngSource::Seeded,
+        RngSource::Fair,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngSource::Thread => "ThreadRng",
+            RngSource::Os => "OsRng",
+            RngSource::Seeded => "ChaCha",
+            RngSource::Fair => "Fair",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            RngSource::Thread => "Fast generator of the thread, reseeded from the system.",
+            RngSource::Os => "Every number straight from the operating system.",
+            RngSource::Seeded => "ChaCha20 from a seed: the same seed gives the same rolls.",
+            RngSource::Fair => {
+                "Committed to before the session and revealed after it, so the rolls can be checked."
+            }
+        }
+    }
+}
+
+/// Where a seeded roll started, enough to roll it again exactly.
+#[derive(Clone, Copy)]
+pub struct Replay {
+    pub seed: u64,
+    /// Position in the ChaCha stream, in 32-bit words.
+    pub word_pos: u128,
+}
+
+impl Replay {
+    pub fn rng(&self) -> ChaCha20Rng {
+        let mut rng = ChaCha20Rng::seed_from_u64(self.seed);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+/// Where a roll came from, kept in its record.
+pub struct RollOrigin {
+    replay: Option<Replay>,
+    fair: Option<FairRoll>,
+}
+
+/// The generator chosen in the settings.
+pub struct DiceRng {
+    source: RngSource,
+    thread: rand::rngs::ThreadRng,
+    os: rand::rngs::OsRng,
+    seed: u64,
+    chacha: ChaCha20Rng,
+    fair: FairSession,
+    /// Of the last fair roll.
+    fair_rng: ChaCha20Rng,
+    /// Every number given out since the roll started, to be saved with its record.
+    tape: Option<Vec<u64>>,
+}
+
+impl DiceRng {
+    pub fn new() -> DiceRng {
+        let mut thread = rand::thread_rng();
+        let seed = thread.gen();
+        DiceRng {
+            source: RngSource::Thread,
+            thread,
+            os: rand::rngs::OsRng,
+            seed,
+            chacha: ChaCha20Rng::seed_from_u64(seed),
+            fair: FairSession::new(),
+            fair_rng: ChaCha20Rng::from_entropy(),
+            tape: None,
+        }
+    }
+
+    /// Starts the seeded stream over from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.chacha = ChaCha20Rng::seed_from_u64(seed);
+    }
+
+    /// To be called right before a roll: a fair roll gets its own generator here.
+    pub fn start(&mut self) -> RollOrigin {
+        self.tape = Some(Vec::new());
+        match self.source {
+            RngSource::Seeded => RollOrigin {
+                replay: Some(Replay {
+                    seed: self.seed,
+                    word_pos: self.chacha.get_word_pos(),
+                }),
+                fair: None,
+            },
+            RngSource::Fair => {
+                let (fair, rng) = self.fair.next();
+                self.fair_rng = rng;
+                RollOrigin {
+                    replay: None,
+                    fair: Some(fair),
+                }
+            }
+            RngSource::Thread | RngSource::Os => RollOrigin {
+                replay: None,
+                fair: None,
+            },
+        }
+    }
+
+    /// Rolls with `roll` and records where it started.
+    pub fn replayable(
+        &mut self,
+        roll: impl FnOnce(&mut DiceRng) -> Box<super::RollRecord>,
+    ) -> Box<super::RollRecord> {
+        let origin = self.start();
+        let mut record = roll(self);
+        self.finish(origin, &mut record);
+        record
+    }
+
+    /// To be called right after a roll begun with [`DiceRng::start`].
+    pub fn finish(&mut self, origin: RollOrigin, record: &mut super::RollRecord) {
+        record.replay = origin.replay;
+        record.fair = origin.fair;
+        record.tape = self.tape.take().unwrap_or_default();
+    }
+
+    fn source(&mut self) -> &mut dyn RngCore {
+        match self.source {
+            RngSource::Thread => &mut self.thread,
+            RngSource::Os => &mut self.os,
+            RngSource::Seeded => &mut self.chacha,
+            RngSource::Fair => &mut self.fair_rng,
+        }
+    }
+
+    fn record(&mut self, values: impl Iterator<Item = u64>) {
+        if let Some(tape) = &mut self.tape {
+            tape.extend(values);
+        }
+    }
+}
+
+/// Little-endian, the bytes past `chunk` being zeros.
+fn bytes_to_u64(chunk: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[..chunk.len()].copy_from_slice(chunk);
+    u64::from_le_bytes(bytes)
+}
+
+/// Gives out the numbers taped by [`DiceRng`] during a roll again, then zeros.
+pub struct Tape<'a>(pub std::slice::Iter<'a, u64>);
+
+impl RngCore for Tape<'_> {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next().copied().unwrap_or(0)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl RngCore for DiceRng {
+    fn next_u32(&mut self) -> u32 {
+        let v = self.source().next_u32();
+        self.record(std::iter::once(v as u64));
+        v
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let v = self.source().next_u64();
+        self.record(std::iter::once(v));
+        v
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.source().fill_bytes(dest);
+        self.record(dest.chunks(8).map(bytes_to_u64));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.source().try_fill_bytes(dest)?;
+        self.record(dest.chunks(8).map(bytes_to_u64));
+        Ok(())
+    }
+}
+
+pub struct RngWindow {
+    pub is_show: bool,
+
+    seed_input: String,
+    seed_error: Option<String>,
+}
+
+impl RngWindow {
+    pub fn new() -> RngWindow {
+        RngWindow {
+            is_show: false,
+            seed_input: String::new(),
+            seed_error: None,
+        }
+    }
+
+    /// The end of a fair session when its secret seed is revealed.
+    pub fn update(&mut self, rd: &mut DiceRng, ctx: &egui::CtxRef) -> Option<Revealed> {
+        let mut revealed = None;
+        let mut is_show = self.is_show;
+        egui::Window::new("Random source")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for source in RngSource::ALL {
+                        ui.selectable_value(&mut rd.source, source, source.name())
+                            .on_hover_text(source.description());
+                    }
+                });
+
+                if rd.source == RngSource::Fair {
+                    revealed = RngWindow::show_fair(ui, rd);
+                }
+                if rd.source != RngSource::Seeded {
+                    return;
+                }
+                ui.separator();
+                egui::Grid::new("rng_seed").show(ui, |ui| {
+                    ui.strong("Seed");
+                    ui.horizontal(|ui| {
+                        ui.label(rd.seed.to_string());
+                        if ui.small_button("copy").clicked() {
+                            ui.output().copied_text = rd.seed.to_string();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.strong("Position");
+                    ui.label(rd.chacha.get_word_pos().to_string())
+                        .on_hover_text("Words of the stream used since the seed was set.");
+                    ui.end_row();
+
+                    ui.strong("New seed");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.seed_input)
+                            .hint_text("number")
+                            .desired_width(160.0),
+                    );
+                    ui.end_row();
+                });
+                if let Some(e) = &self.seed_error {
+                    ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Set").clicked() {
+                        match self.seed_input.trim().parse() {
+                            Ok(seed) => {
+                                rd.reseed(seed);
+                                self.seed_error = None;
+                            }
+                            Err(_) => {
+                                self.seed_error =
+                                    Some("The seed is a whole number from 0.".to_string())
+                            }
+                        }
+                    }
+                    if ui.button("Random").clicked() {
+                        let seed = rd.thread.gen();
+                        rd.reseed(seed);
+                    }
+                    if ui
+                        .button("Restart")
+                        .on_hover_text("Roll the same numbers again from the seed.")
+                        .clicked()
+                    {
+                        rd.reseed(rd.seed);
+                    }
+                });
+            });
+        self.is_show = is_show;
+        revealed
+    }
+
+    fn show_fair(ui: &mut egui::Ui, rd: &mut DiceRng) -> Option<Revealed> {
+        ui.separator();
+        egui::Grid::new("rng_fair").show(ui, |ui| {
+            ui.strong("Commitment");
+            ui.horizontal(|ui| {
+                let commitment = rd.fair.commitment().to_string();
+                ui.monospace(commitment.get(..16).unwrap_or_default())
+                    .on_hover_text(format!("SHA-256 of the secret seed: {}", commitment));
+                if ui.small_button("copy").clicked() {
+                    ui.output().copied_text = commitment;
+                }
+            });
+            ui.end_row();
+
+            ui.strong("Player seed");
+            match rd.fair.player_seed_mut() {
+                Some(player_seed) => ui
+                    .add(
+                        egui::TextEdit::singleline(player_seed)
+                            .hint_text("from the players")
+                            .desired_width(160.0),
+                    )
+                    .on_hover_text("Chosen by the players after the commitment is published."),
+                None => ui
+                    .monospace(rd.fair.player_seed())
+                    .on_hover_text("Fixed since the first roll, until the seed is revealed."),
+            };
+            ui.end_row();
+
+            ui.strong("Rolls");
+            ui.label(rd.fair.nonce().to_string());
+            ui.end_row();
+        });
+        ui.label(
+            egui::RichText::new(
+                "The secret seed is only kept in memory. Reveal it before closing the app, \
+                or the rolls can never be checked.",
+            )
+            .color(egui::Color32::from_rgb(230, 120, 0)),
+        );
+
+        let reveal = ui
+            .button("Reveal")
+            .on_hover_text("End the session, show its secret seed and check its rolls.");
+        if reveal.clicked() {
+            let session = std::mem::replace(&mut rd.fair, FairSession::new());
+            Some(session.reveal())
+        } else {
+            None
+        }
+    }
+}
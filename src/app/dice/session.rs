@@ -0,0 +1,303 @@
+//! Named play sessions, each with its own history file in [`dir`].
+
+use super::export::{self, ExportFormat};
+use super::history;
+use eframe::egui;
+use std::path::{Path, PathBuf};
+
+const DIR: &str = "sessions";
+
+/// Names Windows keeps for devices, whatever the extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The directory of the session files, next to the program.
+pub fn dir() -> PathBuf {
+    history::app_dir().join(DIR)
+}
+
+/// Characters kept in the file name of a session, the others become `_`. A reserved
+/// name gets a `_` after it.
+fn slug(name: &str) -> String {
+    let slug: String = name
+        .trim()
+        .chars()
+        .take(64)
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if RESERVED_NAMES.contains(&slug.to_uppercase().as_str()) {
+        slug + "_"
+    } else {
+        slug
+    }
+}
+
+/// The file of the session `name`: the one already used for it, or a new one.
+pub fn path_of(name: &str) -> PathBuf {
+    let slug = slug(name);
+    let dir = dir();
+    (1..)
+        .map(|i| match i {
+            1 => dir.join(format!("{}.txt", slug)),
+            _ => dir.join(format!("{}_{}.txt", slug, i)),
+        })
+        .find(|path| match history::summarize(path) {
+            Some(summary) => summary.name.as_deref() == Some(name.trim()),
+            None => !path.exists(),
+        })
+        .unwrap()
+}
+
+/// The sessions in [`dir`], the last played first.
+fn list() -> Vec<history::Summary> {
+    let mut sessions: Vec<history::Summary> = std::fs::read_dir(dir())
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| history::summarize(&e.path()))
+                .filter(|s| s.name.is_some())
+                .collect()
+        })
+        .unwrap_or_default();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last));
+    sessions
+}
+
+/// What the user asked the record panel to do.
+pub enum SessionAction {
+    /// Start or go on with the session at `path` named `name`.
+    Open {
+        path: PathBuf,
+        name: String,
+    },
+    End,
+}
+
+/// A previous session opened for reading.
+struct Viewed {
+    path: PathBuf,
+    log: Result<history::Log, String>,
+}
+
+pub struct SessionWindow {
+    pub is_show: bool,
+
+    new_name: String,
+    sessions: Vec<history::Summary>,
+    viewed: Option<Viewed>,
+    format: ExportFormat,
+    /// What the last export or deletion did, or why it failed.
+    message: Option<Result<String, String>>,
+}
+
+impl Default for SessionWindow {
+    fn default() -> SessionWindow {
+        SessionWindow::new()
+    }
+}
+
+impl SessionWindow {
+    pub fn new() -> SessionWindow {
+        SessionWindow {
+            is_show: false,
+            new_name: String::new(),
+            sessions: Vec::new(),
+            viewed: None,
+            format: ExportFormat::Markdown,
+            message: None,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.is_show = true;
+        self.sessions = list();
+    }
+
+    /// `current` is the path of the session being played.
+    pub fn update(&mut self, current: Option<&Path>, ctx: &egui::CtxRef) -> Option<SessionAction> {
+        let mut action = None;
+        let mut is_show = self.is_show;
+        egui::Window::new("Sessions")
+            .auto_sized()
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_name)
+                            .hint_text("Masks of Nyarlathotep #12")
+                            .desired_width(200.0),
+                    );
+                    let name = self.new_name.trim().to_string();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("Start"))
+                        .clicked()
+                    {
+                        let dir = dir();
+                        if let Err(e) = std::fs::create_dir_all(&dir) {
+                            self.message = Some(Err(format!("{}: {}", dir.display(), e)));
+                            return;
+                        }
+                        action = Some(SessionAction::Open {
+                            path: path_of(&name),
+                            name,
+                        });
+                        self.new_name.clear();
+                    }
+                    if current.is_some() && ui.button("End").clicked() {
+                        action = Some(SessionAction::End);
+                    }
+                });
+
+                ui.separator();
+                if ui.small_button("refresh").clicked() {
+                    self.sessions = list();
+                }
+                self.show_list(ui, current, &mut action);
+                self.show_viewed(ui, current);
+
+                match &self.message {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(e)) => {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+                    None => {}
+                }
+            });
+        self.is_show = is_show;
+        if action.is_some() {
+            self.sessions = list();
+        }
+        action
+    }
+
+    fn show_list(
+        &mut self,
+        ui: &mut egui::Ui,
+        current: Option<&Path>,
+        action: &mut Option<SessionAction>,
+    ) {
+        if self.sessions.is_empty() {
+            ui.label(egui::RichText::new("No session yet.").weak());
+            return;
+        }
+        let time = |t: &Option<chrono::DateTime<chrono::Local>>| {
+            t.map_or(String::new(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+        };
+        egui::ScrollArea::vertical()
+            .id_source("session_list")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                egui::Grid::new("session_list")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for s in self.sessions.iter() {
+                            let name = s.name.clone().unwrap_or_default();
+                            let is_current = current == Some(s.path.as_path());
+                            let is_viewed = self.viewed.as_ref().map(|v| &v.path) == Some(&s.path);
+                            if ui.selectable_label(is_viewed, &name).clicked() {
+                                self.viewed = Some(Viewed {
+                                    path: s.path.clone(),
+                                    log: history::read(&s.path, usize::MAX),
+                                });
+                            }
+                            ui.label(format!("{} rolls", s.rolls));
+                            ui.label(format!("{} ~ {}", time(&s.first), time(&s.last)));
+                            if is_current {
+                                ui.strong("playing");
+                            } else if ui.small_button("resume").clicked() {
+                                *action = Some(SessionAction::Open {
+                                    path: s.path.clone(),
+                                    name,
+                                });
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    /// Rolls of the session picked in the list, to export or delete it.
+    fn show_viewed(&mut self, ui: &mut egui::Ui, current: Option<&Path>) {
+        let viewed = match &self.viewed {
+            Some(viewed) => viewed,
+            None => return,
+        };
+        ui.separator();
+        let log = match &viewed.log {
+            Ok(log) => log,
+            Err(e) => {
+                ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                return;
+            }
+        };
+
+        ui.heading(log.name.as_deref().unwrap_or_default());
+        if let Some(notice) = log.notice(&viewed.path) {
+            ui.label(egui::RichText::new(notice).color(egui::Color32::RED));
+        }
+        egui::ScrollArea::vertical()
+            .id_source("session_rolls")
+            .max_height(240.0)
+            .show(ui, |ui| {
+                egui::Grid::new("session_rolls")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for record in log.records.iter() {
+                            ui.label(record.time.format("%m-%d %H:%M:%S").to_string());
+                            ui.label(&record.description);
+                            ui.strong(record.result_text());
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        ui.horizontal(|ui| {
+            for format in ExportFormat::ALL {
+                ui.selectable_value(&mut self.format, format, format.name());
+            }
+        });
+        let is_current = current == Some(viewed.path.as_path());
+        let mut delete = false;
+        ui.horizontal(|ui| {
+            if ui.button("Copy").clicked() {
+                ui.output().copied_text = self.format.export(log.records.iter());
+                self.message = Some(Ok(format!("Copied {} rolls.", log.records.len())));
+            }
+            if ui.button("Save").clicked() {
+                // Not `.txt`, which is the extension of the session itself.
+                let path = viewed
+                    .path
+                    .with_extension(format!("export.{}", self.format.extension()));
+                let text = self.format.export(log.records.iter());
+                self.message = Some(export::save(&path, &text, log.records.len()));
+            }
+            let delete_button =
+                egui::Button::new(egui::RichText::new("Delete").color(egui::Color32::RED));
+            let response = ui
+                .add_enabled(!is_current, delete_button)
+                .on_hover_text("Double-Click to delete the whole session.")
+                .on_disabled_hover_text("End the session first.");
+            delete = response.double_clicked();
+        });
+
+        if delete {
+            let path = viewed.path.clone();
+            self.message = Some(match std::fs::remove_file(&path) {
+                Ok(_) => Ok(format!("Deleted {}.", path.display())),
+                Err(e) => Err(format!("{}: {}", path.display(), e)),
+            });
+            self.viewed = None;
+            self.sessions = list();
+        }
+    }
+}
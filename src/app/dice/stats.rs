@@ -0,0 +1,519 @@
+//! Exact probability distributions of rolls, computed by convolution.
+
+use super::expr::Expr;
+use super::{DiceGroup, DicesState, KeepRule, PoolRule, MAX_EXPLOSIONS, MAX_REROLLS};
+use eframe::egui;
+use std::collections::BTreeMap;
+
+/// Work allowed for one distribution, in multiplications, before giving up on it.
+const MAX_COST: f64 = 3e7;
+/// Exploding chains stop being followed once they are less likely than this.
+const NEGLIGIBLE: f64 = 1e-15;
+/// Above this many values the histogram is drawn as a line instead of bars.
+const MAX_BARS: usize = 120;
+
+/// Multiplications left before a distribution is too expensive to compute.
+struct Budget(f64);
+
+impl Budget {
+    fn spend(&mut self, cost: f64) -> Option<()> {
+        self.0 -= cost;
+        if self.0 < 0.0 {
+            None
+        } else {
+            Some(())
+        }
+    }
+}
+
+/// Probabilities of the integers `min..min + probs.len()`.
+#[derive(Clone)]
+pub struct Distribution {
+    pub min: i32,
+    pub probs: Vec<f64>,
+}
+
+impl Distribution {
+    /// The distribution of a single roll of `state`, or `None` if it is too expensive
+    /// to compute. Explosions are followed until their chance is negligible.
+    pub fn of(state: &DicesState) -> Option<Distribution> {
+        let mut budget = Budget(MAX_COST);
+        let groups = state
+            .groups
+            .iter()
+            .map(|g| Distribution::of_group(g, &mut budget))
+            .collect::<Option<Vec<_>>>()?;
+
+        match &state.formula {
+            Some(formula) => {
+                let values = Sparse::of(formula, &groups, &mut budget)?;
+                Some(Distribution::from_values(
+                    values.0.iter().map(|&(v, p)| (v.floor() as i32, p)),
+                ))
+            }
+            None => {
+                let mut total = Distribution::constant(state.constant);
+                for g in &groups {
+                    budget.spend((total.probs.len() * g.probs.len()) as f64)?;
+                    total = total.convolve(g);
+                }
+                Some(total)
+            }
+        }
+    }
+
+    /// Whether [`Distribution::of`] handles every group of `state`. Pools keeping dice
+    /// by face are not.
+    pub fn is_supported(state: &DicesState) -> bool {
+        !state
+            .groups
+            .iter()
+            .any(|g| g.count != 0 && g.pool.is_some() && g.keep.is_some())
+    }
+
+    fn constant(v: i32) -> Distribution {
+        Distribution {
+            min: v,
+            probs: vec![1.0],
+        }
+    }
+
+    fn from_values(values: impl Iterator<Item = (i32, f64)>) -> Distribution {
+        let map: BTreeMap<i32, f64> = values.fold(BTreeMap::new(), |mut map, (v, p)| {
+            *map.entry(v).or_insert(0.0) += p;
+            map
+        });
+        let min = *map.keys().next().unwrap_or(&0);
+        let max = *map.keys().next_back().unwrap_or(&0);
+        let mut probs = vec![0.0; (max - min + 1) as usize];
+        for (v, p) in map {
+            probs[(v - min) as usize] = p;
+        }
+        Distribution { min, probs }
+    }
+
+    pub fn max(&self) -> i32 {
+        self.min + self.probs.len() as i32 - 1
+    }
+
+    /// Every value with its probability.
+    pub fn values(&self) -> impl Iterator<Item = (i32, f64)> + '_ {
+        self.probs
+            .iter()
+            .enumerate()
+            .map(move |(i, &p)| (self.min + i as i32, p))
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.values().map(|(v, p)| v as f64 * p).sum()
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        let variance: f64 = self
+            .values()
+            .map(|(v, p)| (v as f64 - mean).powi(2) * p)
+            .sum();
+        variance.sqrt()
+    }
+
+    /// Chance of rolling `v` or more.
+    pub fn at_least(&self, v: i32) -> f64 {
+        self.values().filter(|&(x, _)| x >= v).map(|(_, p)| p).sum()
+    }
+
+    /// Where `v` falls from 0 to 100, counting half of the rolls equal to it.
+    pub fn percentile(&self, v: i32) -> f64 {
+        let (below, equal) = self.values().fold((0.0, 0.0), |(below, equal), (x, p)| {
+            if x < v {
+                (below + p, equal)
+            } else if x == v {
+                (below, equal + p)
+            } else {
+                (below, equal)
+            }
+        });
+        (below + equal / 2.0) * 100.0
+    }
+
+    /// Percentile of `v` as a small label, highlighted when it is far from the middle.
+    pub fn show_badge(&self, ui: &mut egui::Ui, v: i32) {
+        let percentile = self.percentile(v);
+        let text = egui::RichText::new(format!("p{:.0}", percentile)).small();
+        let text = if percentile >= 90.0 {
+            text.color(egui::Color32::GOLD)
+        } else if percentile <= 10.0 {
+            text.color(egui::Color32::GRAY)
+        } else {
+            text.weak()
+        };
+        ui.label(text).on_hover_text(format!(
+            "Higher than {:.1}% of the possible rolls, counting half of the ties.",
+            percentile
+        ));
+    }
+
+    fn negate(&self) -> Distribution {
+        Distribution {
+            min: -self.max(),
+            probs: self.probs.iter().rev().copied().collect(),
+        }
+    }
+
+    fn convolve(&self, other: &Distribution) -> Distribution {
+        let mut probs = vec![0.0; self.probs.len() + other.probs.len() - 1];
+        for (i, &p) in self.probs.iter().enumerate().filter(|(_, &p)| p != 0.0) {
+            for (j, &q) in other.probs.iter().enumerate() {
+                probs[i + j] += p * q;
+            }
+        }
+        Distribution {
+            min: self.min + other.min,
+            probs,
+        }
+    }
+
+    /// Drops the negligible values at both ends.
+    fn trim(&mut self) {
+        let start = self
+            .probs
+            .iter()
+            .position(|&p| p >= NEGLIGIBLE)
+            .unwrap_or(0);
+        let end = self
+            .probs
+            .iter()
+            .rposition(|&p| p >= NEGLIGIBLE)
+            .map_or(self.probs.len(), |i| i + 1);
+        self.probs = self.probs[start..end.max(start + 1)].to_vec();
+        self.min += start as i32;
+    }
+
+    /// Sum of `count` plain dice, adding one dice at a time over a sliding window.
+    fn uniform_sum(count: i32, sides: i32, budget: &mut Budget) -> Option<Distribution> {
+        // Each dice costs about the length of the sums so far.
+        budget.spend(count as f64 * count as f64 * sides as f64 / 2.0)?;
+        let sides = sides as usize;
+        let mut probs = vec![1.0];
+        for _ in 0..count {
+            let mut next = vec![0.0; probs.len() + sides - 1];
+            let mut window = 0.0;
+            for (i, p) in next.iter_mut().enumerate() {
+                if i < probs.len() {
+                    window += probs[i];
+                }
+                if i >= sides {
+                    window -= probs[i - sides];
+                }
+                *p = window / sides as f64;
+            }
+            probs = next;
+        }
+        Some(Distribution { min: count, probs })
+    }
+
+    /// Sum of `count` independent rolls of `die`.
+    fn sum_of(die: &Distribution, count: i32, budget: &mut Budget) -> Option<Distribution> {
+        let mut total = Distribution::constant(0);
+        for _ in 0..count {
+            budget.spend((total.probs.len() * die.probs.len()) as f64)?;
+            total = total.convolve(die);
+            total.trim();
+        }
+        Some(total)
+    }
+
+    fn of_group(g: &DiceGroup, budget: &mut Budget) -> Option<Distribution> {
+        if g.count == 0 {
+            return Some(Distribution::constant(0));
+        }
+        let plain = g.reroll.is_none() && g.explode.is_none();
+        let dist = match (g.pool, g.keep) {
+            // Dice are kept by face, which the scores no longer tell.
+            (Some(_), Some(_)) => return None,
+            (Some(pool), None) => {
+                Distribution::sum_of(&Distribution::die_score(g, pool, budget)?, g.count, budget)?
+            }
+            (None, Some(keep)) => {
+                Distribution::keep(&Distribution::die(g, budget)?, g.count, keep, budget)?
+            }
+            (None, None) if plain => Distribution::uniform_sum(g.count, g.sides, budget)?,
+            (None, None) => Distribution::sum_of(&Distribution::die(g, budget)?, g.count, budget)?,
+        };
+        Some(if g.negative { dist.negate() } else { dist })
+    }
+
+    /// Chance of each face on the first roll of a dice of `g`, after its rerolls.
+    fn first_roll(g: &DiceGroup) -> Vec<f64> {
+        let sides = g.sides as f64;
+        let reroll = match &g.reroll {
+            Some(reroll) => reroll,
+            None => return vec![1.0 / sides; g.sides as usize],
+        };
+        // The last reroll keeps whatever it shows.
+        let limit = if reroll.once { 1 } else { MAX_REROLLS as i32 };
+        let q = (1..=g.sides).filter(|&v| reroll.matches(v)).count() as f64 / sides;
+        let kept = if q < 1.0 {
+            (1.0 - q.powi(limit)) / (1.0 - q)
+        } else {
+            limit as f64
+        };
+        (1..=g.sides)
+            .map(|v| {
+                let first = if reroll.matches(v) { 0.0 } else { kept };
+                (first + q.powi(limit)) / sides
+            })
+            .collect()
+    }
+
+    /// Follow the exploding chain of one dice. `score` maps each roll to what it adds up.
+    fn chain(
+        g: &DiceGroup,
+        score: impl Fn(i32) -> i32,
+        budget: &mut Budget,
+    ) -> Option<Distribution> {
+        let uniform = vec![1.0 / g.sides as f64; g.sides as usize];
+        let mut faces = Distribution::first_roll(g);
+        let mut done = BTreeMap::new();
+        let mut pending = BTreeMap::from([(0, 1.0)]);
+        for depth in 0..=MAX_EXPLOSIONS {
+            budget.spend((pending.len() * faces.len()) as f64)?;
+            let mut next = BTreeMap::new();
+            for (&sum, &p) in &pending {
+                for (face, &q) in (1..=g.sides).zip(&faces) {
+                    let explodes = matches!(g.explode, Some(e) if e.explodes(face, g.sides));
+                    let target = if explodes && depth < MAX_EXPLOSIONS {
+                        &mut next
+                    } else {
+                        &mut done
+                    };
+                    *target.entry(sum + score(face)).or_insert(0.0) += p * q;
+                }
+            }
+            pending = next;
+            faces = uniform.clone();
+            if pending.values().sum::<f64>() < NEGLIGIBLE {
+                break;
+            }
+        }
+        let mut dist = Distribution::from_values(done.into_iter());
+        dist.trim();
+        Some(dist)
+    }
+
+    /// The value of one dice of `g`.
+    fn die(g: &DiceGroup, budget: &mut Budget) -> Option<Distribution> {
+        Distribution::chain(g, |face| face, budget)
+    }
+
+    /// The successes of one dice of a pool.
+    fn die_score(g: &DiceGroup, pool: PoolRule, budget: &mut Budget) -> Option<Distribution> {
+        match g.explode {
+            // A compounded dice counts its sum once.
+            Some(e) if e.compound => {
+                let value = Distribution::die(g, budget)?;
+                Some(Distribution::from_values(
+                    value.values().map(|(v, p)| (pool.score(v), p)),
+                ))
+            }
+            _ => Distribution::chain(g, |face| pool.score(face), budget),
+        }
+    }
+
+    /// Sum of the dice kept out of `count` rolls of `die`.
+    ///
+    /// Goes through the values from the best for the rule to the worst, deciding how
+    /// many of the remaining dice show each one; the first `kept` of them count.
+    fn keep(
+        die: &Distribution,
+        count: i32,
+        rule: KeepRule,
+        budget: &mut Budget,
+    ) -> Option<Distribution> {
+        let n = count as usize;
+        let (kept, highest) = match rule {
+            KeepRule::KeepHighest(k) => (k as usize, true),
+            KeepRule::KeepLowest(k) => (k as usize, false),
+            KeepRule::DropHighest(k) => (n - k as usize, false),
+            KeepRule::DropLowest(k) => (n - k as usize, true),
+        };
+        // Dice values start from 1, so sums index directly.
+        let sums = kept * die.max().max(0) as usize + 1;
+        budget.spend((die.probs.len() * n * n / 2 * sums) as f64)?;
+
+        let mut values: Vec<(i32, f64)> = die.values().filter(|&(_, p)| p != 0.0).collect();
+        if highest {
+            values.reverse();
+        }
+
+        // `dp[i][sum]`: the first `i` dice are decided and the kept ones add up to `sum`.
+        let mut dp = vec![vec![0.0; sums]; n + 1];
+        dp[0][0] = 1.0;
+        for (v, p) in values {
+            let mut next = vec![vec![0.0; sums]; n + 1];
+            for (i, row) in dp.iter().enumerate() {
+                for (sum, &prob) in row.iter().enumerate().filter(|(_, &prob)| prob != 0.0) {
+                    next[i][sum] += prob;
+                    // Ways to pick `j` of the remaining dice, times the chance they show `v`.
+                    let mut ways = 1.0;
+                    for j in 1..=n - i {
+                        ways *= (n - i - j + 1) as f64 / j as f64 * p;
+                        let added = (i + j).min(kept) - i.min(kept);
+                        next[i + j][sum + added * v as usize] += prob * ways;
+                    }
+                }
+            }
+            dp = next;
+        }
+
+        let mut dist = Distribution {
+            min: 0,
+            probs: dp.pop().unwrap(),
+        };
+        dist.trim();
+        Some(dist)
+    }
+
+    /// Mean, deviation and range in a grid, then the histogram with `markers` on it.
+    pub fn show(&self, ui: &mut egui::Ui, id: impl std::hash::Hash, markers: &[i32]) {
+        egui::Grid::new((&id, "summary")).show(ui, |ui| {
+            ui.strong("Mean");
+            ui.label(format!("{:.2}", self.mean()));
+            ui.strong("Std dev");
+            ui.label(format!("{:.2}", self.std_dev()));
+            ui.end_row();
+            ui.strong("Min");
+            ui.label(self.min.to_string());
+            ui.strong("Max");
+            ui.label(self.max().to_string());
+            ui.end_row();
+        });
+
+        use egui::plot::{Bar, BarChart, Line, Plot, VLine, Value, Values};
+        Plot::new(id)
+            .height(160.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                if self.probs.len() <= MAX_BARS {
+                    let bars = self
+                        .values()
+                        .map(|(v, p)| Bar::new(v as f64, p * 100.0).width(0.9))
+                        .collect();
+                    plot_ui.bar_chart(BarChart::new(bars).color(egui::Color32::LIGHT_BLUE));
+                } else {
+                    let points = self
+                        .values()
+                        .map(|(v, p)| Value::new(v as f64, p * 100.0))
+                        .collect();
+                    plot_ui.line(
+                        Line::new(Values::from_values(points))
+                            .color(egui::Color32::LIGHT_BLUE)
+                            .fill(0.0),
+                    );
+                }
+                for &marker in markers {
+                    plot_ui.vline(VLine::new(marker as f64).color(egui::Color32::RED));
+                }
+            });
+    }
+}
+
+/// Values of a formula, which may have fractions until the end.
+struct Sparse(Vec<(f64, f64)>);
+
+impl Sparse {
+    fn of(expr: &Expr, groups: &[Distribution], budget: &mut Budget) -> Option<Sparse> {
+        Some(match expr {
+            Expr::Dice(i) => Sparse(groups[*i].values().map(|(v, p)| (v as f64, p)).collect()),
+            Expr::Constant(c) => Sparse(vec![(*c as f64, 1.0)]),
+            Expr::Neg(e) => Sparse::of(e, groups, budget)?.map(|v| -v),
+            Expr::Round(r, e) => Sparse::of(e, groups, budget)?.map(|v| r.apply(v)),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = Sparse::of(lhs, groups, budget)?;
+                let rhs = Sparse::of(rhs, groups, budget)?;
+                budget.spend((lhs.0.len() * rhs.0.len()) as f64)?;
+                let values = lhs
+                    .0
+                    .iter()
+                    .flat_map(|&(x, p)| rhs.0.iter().map(move |&(y, q)| (op.apply(x, y), p * q)))
+                    .collect();
+                Sparse(values).merged()
+            }
+        })
+    }
+
+    fn map(self, f: impl Fn(f64) -> f64) -> Sparse {
+        Sparse(self.0.into_iter().map(|(v, p)| (f(v), p)).collect()).merged()
+    }
+
+    /// Sorts the values and adds up the chances of equal ones.
+    fn merged(mut self) -> Sparse {
+        self.0.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(self.0.len());
+        for (v, p) in self.0 {
+            match merged.last_mut() {
+                Some(last) if last.0 == v => last.1 += p,
+                _ => merged.push((v, p)),
+            }
+        }
+        Sparse(merged)
+    }
+}
+
+/// Odds of the dice in the Selections panel.
+pub struct OddsWindow {
+    pub is_show: bool,
+
+    target: i32,
+    /// Description of the last selection and its distribution, to compute it only once.
+    cache: Option<(String, Option<Distribution>)>,
+}
+
+impl OddsWindow {
+    pub fn new() -> OddsWindow {
+        OddsWindow {
+            is_show: false,
+            target: 10,
+            cache: None,
+        }
+    }
+
+    pub fn update(&mut self, state: &DicesState, ctx: &egui::CtxRef) {
+        let mut is_show = self.is_show;
+        egui::Window::new("Odds")
+            .default_width(320.0)
+            .open(&mut is_show)
+            .show(ctx, |ui| {
+                if !state.valid() {
+                    ui.label("Select some dice to see their odds.");
+                    return;
+                }
+
+                let description = state.gen_description();
+                if !matches!(&self.cache, Some((d, _)) if *d == description) {
+                    self.cache = Some((description.clone(), Distribution::of(state)));
+                }
+                ui.heading(description);
+
+                match &self.cache {
+                    Some((_, Some(dist))) => {
+                        ui.horizontal(|ui| {
+                            ui.label("Chance to meet or beat");
+                            ui.add(egui::DragValue::new(&mut self.target).speed(0.1));
+                            ui.strong(format!("{:.2}%", dist.at_least(self.target) * 100.0));
+                        });
+                        dist.show(ui, "odds_chart", &[self.target]);
+                    }
+                    _ if !Distribution::is_supported(state) => {
+                        ui.label("The odds of a pool keeping or dropping dice are not supported.");
+                    }
+                    _ => {
+                        ui.label("Too many possibilities to compute exactly.");
+                    }
+                }
+            });
+        self.is_show = is_show;
+    }
+}
@@ -131,12 +131,13 @@ impl SoundPlayer {
         *self.is_error_window_show.borrow_mut() = true;
     }
 
-    pub fn play(&self, rd: &mut rand::rngs::ThreadRng) {
+    /// Picks the sound with its own random source, so seeded dice are not disturbed.
+    pub fn play(&self) {
         //println!("play sound{}",index);
         if let Some((_, output)) = &self.output {
             if !self.sounds.is_empty() {
                 if let Err(e) = output.play_raw(
-                    self.sounds[rd.gen_range(0..self.sounds.len())]
+                    self.sounds[rand::thread_rng().gen_range(0..self.sounds.len())]
                         .clone()
                         .amplify(self.volume as f32 / 10.0)
                         .convert_samples(),